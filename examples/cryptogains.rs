@@ -1,6 +1,7 @@
+use costbasis::gain::HoldingPeriod;
 /// Example taking crypto buys and sells to determine realized gains and remaining inventory
-use costbasis::holding::Holding;
-use costbasis::realized::{realized_to_compact, total_realized, Realized};
+use costbasis::portfolio::Portfolio;
+use costbasis::realized::{realized_to_compact, total_realized, total_realized_by_gain_type};
 use costbasis::transaction::Transaction;
 use std::collections::HashMap;
 use std::error::Error;
@@ -17,27 +18,26 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     println!("TRANSACTIONS LOADED");
 
-    let mut symbol_holding_realized: HashMap<String, (Holding, Vec<Realized>)> = HashMap::new();
+    let mut portfolio: Portfolio = Portfolio::default();
 
     for (symbol, transactions) in symbol_transaction_data.iter() {
-        let mut holding = Holding::default();
-        let realized = holding.extend_transactions(transactions);
-        symbol_holding_realized.insert(symbol.to_owned(), (holding, realized));
+        portfolio.extend_transactions(symbol, transactions);
     }
 
     println!("HOLDINGS CALCULATED");
     println!("-------------------------------------------------------------");
 
-    for (symbol, (holding, realized)) in symbol_holding_realized.iter() {
+    for (symbol, holding, realized) in portfolio.iter() {
         println!("SYMBOL: {} __ {}", symbol, holding);
-        println!("REALIZED RETURNS: {:.2}", total_realized(&realized));
-        if !realized.is_empty() {
-            for r in realized_to_compact(&realized).iter() {
-                println!("{}", r);
-            }
+        println!("REALIZED RETURNS: {:.2}", total_realized(realized));
+        let (short, long) = total_realized_by_gain_type(realized, HoldingPeriod::default());
+        println!("SHORT-TERM: {:.2}, LONG-TERM: {:.2}", short, long);
+        for r in realized_to_compact(realized).iter() {
+            println!("{}", r);
         }
         println!("-------------------------------------------------------------");
     }
+    println!("TOTAL REALIZED RETURNS: {:.2}", portfolio.total_realized());
 
     Ok(())
 }
@@ -47,18 +47,26 @@ fn get_transactions(f: File) -> Result<HashMap<String, Vec<Transaction>>, Box<dy
 
     let lines = io::BufReader::new(f).lines();
 
-    for l in lines.skip(1) {
-        let line_data = l.unwrap();
-        let line_data: Vec<&str> = line_data.split(",").collect();
-        let trans_str = [line_data[0], line_data[1], line_data[3], line_data[4]].join(",");
-        if let Some(trans) = transrec.get_mut(line_data[2]) {
-            trans.push(Transaction::from(trans_str.as_str()));
-        } else {
-            transrec.insert(
-                line_data[2].to_owned(),
-                vec![Transaction::from(trans_str.as_str())],
-            );
+    for (i, l) in lines.enumerate().skip(1) {
+        let line_data = l?;
+        let line_data: Vec<&str> = line_data.split(',').collect();
+        if line_data.len() < 5 {
+            eprintln!("SKIPPING LINE {}: missing fields", i + 1);
+            continue;
         }
+        let trans_str = [line_data[0], line_data[1], line_data[3], line_data[4]].join(",");
+        // a malformed row is reported and skipped instead of stopping the import
+        let transaction = match trans_str.parse::<Transaction>() {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("SKIPPING LINE {}: {}", i + 1, e);
+                continue;
+            }
+        };
+        transrec
+            .entry(line_data[2].to_owned())
+            .or_default()
+            .push(transaction);
     }
     Ok(transrec)
 }
@@ -0,0 +1,513 @@
+use crate::config::LotTracking;
+use crate::error::Error;
+use crate::holding::Holding;
+use crate::inventory::{Inventory, InventoryType, VolumeSplit};
+use crate::lot::LotRef;
+use crate::num::Number;
+use crate::portfolio::Portfolio;
+use crate::realized::Realized;
+use crate::unrealized::URealized;
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Holdings of many accounts, such as brokerage accounts or crypto wallets, each with its own
+/// `Portfolio` and so its own `Holding` per asset.
+///
+/// A transfer between accounts moves the specific lots of an asset, with their original open date and
+/// basis, from one account to the other without realizing a gain/loss.  The holding of an asset new to
+/// an account starts as a copy of the holding the accounts were created with.
+///
+/// With `LotTracking::Universal` the lots of all accounts are pooled in one holding per asset and only
+/// the quantity held by each account is kept, until `allocate_to_accounts` switches to tracking each
+/// account separately.  An account can't relieve more pooled lots than the quantity it holds.
+#[derive(Debug, Clone)]
+pub struct Accounts<N = f64>
+where
+    N: Number,
+{
+    start: Holding<N>,
+    tracking: LotTracking,
+    accounts: BTreeMap<String, Portfolio<N>>,
+    pool: Portfolio<N>,
+    // quantity held by (account, asset) with universal tracking
+    balances: BTreeMap<(String, String), N>,
+}
+
+impl<N> Default for Accounts<N>
+where
+    N: Number,
+{
+    fn default() -> Self {
+        Accounts::new(Holding::default())
+    }
+}
+
+impl<N> Accounts<N>
+where
+    N: Number,
+{
+    /// No accounts, the holding of each asset of an account starts as a copy of `holding`
+    pub fn new(holding: Holding<N>) -> Self {
+        Accounts::with_tracking(holding, LotTracking::default())
+    }
+
+    /// Same as `new` with lots tracked following `tracking`
+    pub fn with_tracking(holding: Holding<N>, tracking: LotTracking) -> Self {
+        Accounts {
+            pool: Portfolio::new(holding.clone()),
+            start: holding,
+            tracking,
+            accounts: BTreeMap::new(),
+            balances: BTreeMap::new(),
+        }
+    }
+
+    pub fn tracking(&self) -> LotTracking {
+        self.tracking
+    }
+
+    /// Adds an inventory change of `asset` in `account`.
+    ///
+    /// Panics the same as `Holding::add_transaction`.  Use `try_add_transaction` to handle the error
+    /// instead.
+    pub fn add_transaction<T>(&mut self, account: &str, asset: &str, inv: &T) -> Vec<Realized<N>>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        match self.try_add_transaction(account, asset, inv) {
+            Ok(realized) => realized,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Same as `add_transaction` but returns an `Error` when the inventory change can't be matched
+    pub fn try_add_transaction<T>(
+        &mut self,
+        account: &str,
+        asset: &str,
+        inv: &T,
+    ) -> Result<Vec<Realized<N>>, Error>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        self.try_extend_transactions(account, asset, std::slice::from_ref(inv))
+    }
+
+    /// Adds a series of inventory changes of `asset` in `account` the same as
+    /// `Portfolio::try_extend_transactions`
+    pub fn try_extend_transactions<T>(
+        &mut self,
+        account: &str,
+        asset: &str,
+        invs: &[T],
+    ) -> Result<Vec<Realized<N>>, Error>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        if self.tracking == LotTracking::Universal {
+            // balance of the changes applied to the pool, an account isn't added by a change in error
+            let key = (account.to_owned(), asset.to_owned());
+            let mut balance = self.balances.get(&key).copied();
+            let result = self.pool.extend_with(asset, invs, |holding, inv| {
+                // pooled lots relieved can't be more than the account holds
+                if let Some(direction) = holding.direction() {
+                    let held = match direction {
+                        InventoryType::Long => balance.unwrap_or_else(N::zero),
+                        _ => -balance.unwrap_or_else(N::zero),
+                    };
+                    let disposed = inv.quantity().abs();
+                    if direction != inv.direction_type() && held + N::margin() < disposed {
+                        return Err(Error::InsufficientHolding(
+                            inv.date(),
+                            disposed.to_f64(),
+                            held.to_f64(),
+                        ));
+                    }
+                }
+                let r = holding.try_add_transaction(inv)?;
+                *balance.get_or_insert_with(N::zero) += inv.quantity();
+                Ok(r)
+            });
+            if let Some(balance) = balance {
+                self.balances.insert(key, balance);
+            }
+            return result;
+        }
+        let start = &self.start;
+        self.accounts
+            .entry(account.to_owned())
+            .or_insert_with(|| Portfolio::new(start.clone()))
+            .try_extend_transactions(asset, invs)
+    }
+
+    /// Transfers `quantity` of `asset` from account `from` to account `to` on `date`.
+    ///
+    /// Lots leave `from` following `Holding::transfer_out` and keep their open date and basis in `to`.
+    /// The lots moved are returned and both accounts are left unchanged on an error.
+    ///
+    /// With `LotTracking::Universal` the lots stay in the pool and only the quantity held moves, so no
+    /// lots are returned and `lot` isn't used.
+    pub fn transfer(
+        &mut self,
+        asset: &str,
+        from: &str,
+        to: &str,
+        date: NaiveDate,
+        quantity: N,
+        lot: Option<LotRef>,
+    ) -> Result<Vec<URealized<N>>, Error> {
+        if quantity <= N::zero() {
+            return Err(Error::TransferQuantity(date, quantity.to_f64()));
+        }
+        if self.tracking == LotTracking::Universal {
+            let held = self.balance(from, asset);
+            if held.abs() + N::margin() < quantity {
+                return Err(Error::InsufficientHolding(
+                    date,
+                    quantity.to_f64(),
+                    held.to_f64(),
+                ));
+            }
+            let moved = if held < N::zero() {
+                -quantity
+            } else {
+                quantity
+            };
+            *self.balance_mut(from, asset) -= moved;
+            *self.balance_mut(to, asset) += moved;
+            return Ok(Vec::new());
+        }
+        let mut source = self.holding_or_start(from, asset);
+        let lots = source.transfer_out(date, quantity, lot)?;
+        let mut destination = if from == to {
+            source.clone()
+        } else {
+            self.holding_or_start(to, asset)
+        };
+        destination.transfer_in(date, &lots)?;
+        if from != to {
+            self.portfolio_mut(from).set_holding(asset, source);
+        }
+        self.portfolio_mut(to).set_holding(asset, destination);
+        Ok(lots)
+    }
+
+    /// One time safe harbor allocation of the pooled lots of `LotTracking::Universal` to the accounts on
+    /// the cut-over `date`, after which lots are tracked per account.
+    ///
+    /// The pooled lots of each asset are relieved following the lot selector and allocated to the
+    /// accounts in order of account name, each receiving the quantity it holds on `date`.  Lots keep
+    /// their open date and basis.  Realized gains before the cut-over stay with `pool`.  The quantities
+    /// held by the accounts have to add up to the pooled quantity or it is an `Error::Allocation`, and
+    /// the accounts are left unchanged on an error.  Nothing is done if lots are already tracked per
+    /// account.
+    pub fn allocate_to_accounts(&mut self, date: NaiveDate) -> Result<(), Error> {
+        if self.tracking == LotTracking::PerAccount {
+            return Ok(());
+        }
+        let mut pool = self.pool.clone();
+        let mut accounts = self.accounts.clone();
+        let assets: Vec<String> = pool.assets().map(|a| a.to_owned()).collect();
+        for asset in assets.iter() {
+            let mut holding = pool.holding(asset).unwrap().clone();
+            let pooled = holding.position().0;
+            let held: Vec<(&String, N)> = self
+                .balances
+                .iter()
+                .filter(|((_, a), q)| a == asset && q.abs() > N::margin())
+                .map(|((account, _), q)| (account, *q))
+                .collect();
+            let total: N = held.iter().map(|(_, q)| *q).sum();
+            if (total - pooled).abs() > N::margin()
+                || held
+                    .iter()
+                    .any(|(_, q)| (*q < N::zero()) != (pooled < N::zero()))
+            {
+                return Err(Error::Allocation(
+                    asset.to_owned(),
+                    pooled.to_f64(),
+                    total.to_f64(),
+                ));
+            }
+            for (account, quantity) in held {
+                let lots = holding.transfer_out(date, quantity.abs(), None)?;
+                let mut allocated = self.start.clone();
+                allocated.transfer_in(date, &lots)?;
+                accounts
+                    .entry(account.to_owned())
+                    .or_insert_with(|| Portfolio::new(self.start.clone()))
+                    .set_holding(asset, allocated);
+            }
+            pool.set_holding(asset, holding);
+        }
+        self.pool = pool;
+        self.accounts = accounts;
+        self.balances.clear();
+        self.tracking = LotTracking::PerAccount;
+        Ok(())
+    }
+
+    /// Account names in order
+    pub fn accounts(&self) -> impl Iterator<Item = &str> {
+        self.accounts
+            .keys()
+            .chain(self.balances.keys().map(|(account, _)| account))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|a| a.as_str())
+    }
+
+    /// Portfolio of `account` or `None` if it has no inventory changes or transfers tracked per account
+    pub fn account(&self, account: &str) -> Option<&Portfolio<N>> {
+        self.accounts.get(account)
+    }
+
+    /// Portfolio of the pooled lots of `LotTracking::Universal`, which keeps the realized gains from
+    /// before an allocation to accounts
+    pub fn pool(&self) -> &Portfolio<N> {
+        &self.pool
+    }
+
+    /// Quantity of `asset` held in `account`
+    pub fn balance(&self, account: &str, asset: &str) -> N {
+        match self.tracking {
+            LotTracking::Universal => self
+                .balances
+                .get(&(account.to_owned(), asset.to_owned()))
+                .copied()
+                .unwrap_or_else(N::zero),
+            LotTracking::PerAccount => self
+                .holding(account, asset)
+                .map(|h| h.position().0)
+                .unwrap_or_else(N::zero),
+        }
+    }
+
+    /// Holding of `asset` in `account` or `None` if it hasn't been traded or transferred in the account
+    pub fn holding(&self, account: &str, asset: &str) -> Option<&Holding<N>> {
+        self.account(account).and_then(|p| p.holding(asset))
+    }
+
+    /// Position of `asset` across all accounts: `(quantity, price, basis)`
+    pub fn position(&self, asset: &str) -> (N, N, N) {
+        let (mut q, mut b) = (N::zero(), N::zero());
+        let portfolios = self.accounts.values().chain(std::iter::once(&self.pool));
+        for (pq, _, pb) in portfolios.filter_map(|p| p.position(asset)) {
+            q += pq;
+            b += pb;
+        }
+        let mut p = N::zero();
+        if q.abs() > N::margin() {
+            p = (-b / q).round_dp(self.start.config().price_decimals());
+        }
+        (q, p, b)
+    }
+
+    /// Total realized gain/loss of all accounts
+    pub fn total_realized(&self) -> N {
+        self.pool.total_realized() + self.accounts.values().map(|p| p.total_realized()).sum()
+    }
+
+    fn balance_mut(&mut self, account: &str, asset: &str) -> &mut N {
+        self.balances
+            .entry((account.to_owned(), asset.to_owned()))
+            .or_insert_with(N::zero)
+    }
+
+    // copy of the holding of `asset` in `account`, or the starting holding if there isn't one
+    fn holding_or_start(&self, account: &str, asset: &str) -> Holding<N> {
+        self.holding(account, asset).unwrap_or(&self.start).clone()
+    }
+
+    fn portfolio_mut(&mut self, account: &str) -> &mut Portfolio<N> {
+        let start = &self.start;
+        self.accounts
+            .entry(account.to_owned())
+            .or_insert_with(|| Portfolio::new(start.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::transaction::Transaction;
+
+    fn set_accounts() -> Accounts {
+        let mut accounts = Accounts::default();
+        accounts.add_transaction(
+            "exchange",
+            "BTC",
+            &Transaction::from("2020-01-01,buy,1.0,8000.0"),
+        );
+        accounts.add_transaction(
+            "exchange",
+            "BTC",
+            &Transaction::from("2020-02-01,buy,1.0,9000.0"),
+        );
+        accounts
+    }
+
+    #[test]
+    fn transfer_moves_lots_without_realizing() {
+        let mut accounts = set_accounts();
+        let date = NaiveDate::from_ymd_opt(2020, 3, 1).unwrap();
+        let lots = accounts
+            .transfer("BTC", "exchange", "wallet", date, 1.5, None)
+            .unwrap();
+
+        assert_eq!(
+            lots,
+            [
+                URealized::from("2020-01-01,1.0,-8000.0"),
+                URealized::from("2020-02-01,0.5,-4500.0"),
+            ]
+        );
+        assert_eq!(accounts.holding("wallet", "BTC").unwrap().inventory(), lots);
+        assert_eq!(
+            accounts.holding("exchange", "BTC").unwrap().inventory(),
+            [URealized::from("2020-02-01,0.5,-4500.0")]
+        );
+        assert_eq!(accounts.position("BTC"), (2.0, 8500.0, -17000.0));
+        assert_eq!(accounts.total_realized(), 0.0);
+
+        // transferred lots keep their open date
+        let realized = accounts.add_transaction(
+            "wallet",
+            "BTC",
+            &Transaction::from("2020-04-01,sell,1.0,10000.0"),
+        );
+        assert_eq!(
+            realized,
+            [Realized::from("2020-04-01,-1.0,10000.0,2020-01-01,-8000.0")]
+        );
+    }
+
+    #[test]
+    fn failed_transfer_leaves_accounts_unchanged() {
+        let mut accounts = set_accounts();
+        accounts.add_transaction(
+            "wallet",
+            "BTC",
+            &Transaction::from("2020-03-01,sell,1.0,10000.0"),
+        );
+        let date = NaiveDate::from_ymd_opt(2020, 4, 1).unwrap();
+
+        assert!(matches!(
+            accounts.transfer("BTC", "exchange", "wallet", date, 1.0, None),
+            Err(Error::TransferDirection(_))
+        ));
+        assert!(matches!(
+            accounts.transfer("BTC", "exchange", "cold", date, 3.0, None),
+            Err(Error::InsufficientHolding(_, _, _))
+        ));
+        assert_eq!(
+            accounts.transfer("BTC", "exchange", "cold", date, 0.0, None),
+            Err(Error::TransferQuantity(date, 0.0))
+        );
+        assert_eq!(
+            accounts.transfer("BTC", "exchange", "cold", date, -1.0, None),
+            Err(Error::TransferQuantity(date, -1.0))
+        );
+        assert_eq!(accounts.position("BTC"), (1.0, 7000.0, -7000.0));
+        assert_eq!(
+            accounts.accounts().collect::<Vec<_>>(),
+            ["exchange", "wallet"]
+        );
+    }
+
+    fn set_universal() -> Accounts {
+        let mut accounts = Accounts::with_tracking(Holding::default(), LotTracking::Universal);
+        accounts.add_transaction("a", "BTC", &Transaction::from("2020-01-01,buy,1.0,8000.0"));
+        accounts.add_transaction("b", "BTC", &Transaction::from("2020-02-01,buy,1.0,9000.0"));
+        accounts
+    }
+
+    #[test]
+    fn universal_tracking_pools_lots_of_all_accounts() {
+        let mut accounts = set_universal();
+        // sale in b relieves the oldest lot of the pool bought in a
+        let realized = accounts.add_transaction(
+            "b",
+            "BTC",
+            &Transaction::from("2020-03-01,sell,0.5,10000.0"),
+        );
+        assert_eq!(
+            realized,
+            [Realized::from("2020-03-01,-0.5,5000.0,2020-01-01,-4000.0")]
+        );
+        let date = NaiveDate::from_ymd_opt(2020, 3, 15).unwrap();
+        assert!(accounts
+            .transfer("BTC", "a", "b", date, 0.5, None)
+            .unwrap()
+            .is_empty());
+        assert_eq!(accounts.balance("a", "BTC"), 0.5);
+        assert_eq!(accounts.balance("b", "BTC"), 1.0);
+        assert!(accounts.holding("a", "BTC").is_none());
+        assert_eq!(accounts.position("BTC"), (1.5, 8666.6666666667, -13000.0));
+    }
+
+    #[test]
+    fn safe_harbor_allocates_pool_to_account_balances() {
+        let mut accounts = set_universal();
+        accounts.add_transaction(
+            "b",
+            "BTC",
+            &Transaction::from("2020-03-01,sell,0.5,10000.0"),
+        );
+        accounts
+            .allocate_to_accounts(NaiveDate::from_ymd_opt(2020, 4, 1).unwrap())
+            .unwrap();
+
+        assert_eq!(accounts.tracking(), LotTracking::PerAccount);
+        assert_eq!(
+            accounts.holding("a", "BTC").unwrap().inventory(),
+            [
+                URealized::from("2020-01-01,0.5,-4000.0"),
+                URealized::from("2020-02-01,0.5,-4500.0"),
+            ]
+        );
+        assert_eq!(
+            accounts.holding("b", "BTC").unwrap().inventory(),
+            [URealized::from("2020-02-01,0.5,-4500.0")]
+        );
+        assert_eq!(accounts.pool().realized("BTC").len(), 1);
+        assert_eq!(accounts.total_realized(), 1000.0);
+        assert_eq!(accounts.position("BTC"), (1.5, 8666.6666666667, -13000.0));
+    }
+
+    #[test]
+    fn pooled_sale_larger_than_account_balance_fails() {
+        let mut accounts = set_universal();
+        assert_eq!(
+            accounts.try_add_transaction(
+                "b",
+                "BTC",
+                &Transaction::from("2020-03-01,sell,1.5,10000.0"),
+            ),
+            Err(Error::InsufficientHolding(
+                NaiveDate::from_ymd_opt(2020, 3, 1).unwrap(),
+                1.5,
+                1.0
+            ))
+        );
+        assert_eq!(accounts.balance("b", "BTC"), 1.0);
+        assert_eq!(
+            accounts.pool().position("BTC"),
+            Some((2.0, 8500.0, -17000.0))
+        );
+        assert_eq!(
+            accounts.try_add_transaction(
+                "c",
+                "BTC",
+                &Transaction::from("2020-03-01,sell,0.5,10000.0")
+            ),
+            Err(Error::InsufficientHolding(
+                NaiveDate::from_ymd_opt(2020, 3, 1).unwrap(),
+                0.5,
+                0.0
+            ))
+        );
+        assert_eq!(accounts.accounts().collect::<Vec<_>>(), ["a", "b"]);
+    }
+}
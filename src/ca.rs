@@ -0,0 +1,171 @@
+use crate::error::Error;
+use crate::holding::Holding;
+use crate::inventory::{Inventory, InventoryType, VolumeSplit};
+use crate::num::Number;
+use crate::realized::{AdjustmentCode, Realized};
+use chrono::Duration;
+
+/// Days before and after a disposal at a loss that an acquisition makes the loss superficial
+pub const SUPERFICIAL_LOSS_DAYS: i64 = 30;
+
+/// Adds a batch of inventory changes to `holding` applying the Canadian superficial loss rule.
+///
+/// A loss on a disposal of long inventory is superficial when identical property is acquired within 30
+/// days before or after the disposal and is still held 30 days after the disposal.  The denied share of
+/// the loss is the smallest of the quantity sold, the quantity acquired in the period and the quantity
+/// held at the end of the period, divided by the quantity sold.  An acquisition is substituted property
+/// for one loss only, so the quantity of it already counted for an earlier loss isn't counted again.  The
+/// `Realized` gets a `SuperficialLoss` adjustment of the denied amount, which is added to the adjusted
+/// cost base of the substituted property held, or of the next acquisition when nothing is held.
+///
+/// The holding should use `AverageCost`.  Inventory changes are sorted first with `DateOrder::SortBatch`,
+/// otherwise they have to be sorted by date.  Only acquisitions in the batch are considered, so a loss
+/// early in the batch isn't denied by an acquisition before the batch.  Inventory changes before the one
+/// in error remain applied.
+pub fn superficial_loss<T, N>(
+    holding: &mut Holding<N>,
+    invs: &[T],
+) -> Result<Vec<Realized<N>>, Error>
+where
+    T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    N: Number,
+{
+    let start = holding.position().0;
+    // denied loss waiting for substituted property to be acquired
+    let mut pending = N::zero();
+    // quantity of each acquisition already counted as substituted property of a loss
+    let mut substituted_by = vec![N::zero(); invs.len()];
+    let (realized_return, result) = holding.extend_with(invs, |holding, inv| {
+        let mut realized = holding.try_add_transaction(inv)?;
+        for r in realized.iter_mut() {
+            if r.realized() >= N::zero() || r.quantity() >= N::zero() {
+                continue;
+            }
+            let sold = r.quantity().abs();
+            let first_day = r.close_date() - Duration::days(SUPERFICIAL_LOSS_DAYS);
+            let last_day = r.close_date() + Duration::days(SUPERFICIAL_LOSS_DAYS);
+            let mut acquisitions: Vec<usize> = (0..invs.len())
+                .filter(|&i| invs[i].date() >= first_day && invs[i].date() <= last_day)
+                .filter(|&i| matches!(invs[i].itype(), InventoryType::Long | InventoryType::Add))
+                .collect();
+            acquisitions.sort_by_key(|&i| invs[i].date());
+            let acquired: N = acquisitions
+                .iter()
+                .map(|&i| invs[i].quantity() - substituted_by[i])
+                .sum();
+            let held = start
+                + invs
+                    .iter()
+                    .filter(|i| i.date() <= last_day)
+                    .map(|i| i.quantity())
+                    .sum::<N>();
+            let substituted = sold.min(acquired).min(held);
+            if substituted < N::margin() {
+                continue;
+            }
+            // earliest acquisitions are counted first
+            let mut left = substituted;
+            for &i in acquisitions.iter() {
+                let counted = left.min(invs[i].quantity() - substituted_by[i]);
+                substituted_by[i] += counted;
+                left -= counted;
+            }
+            let denied = -r.realized() * substituted / sold;
+            r.adjust(AdjustmentCode::SuperficialLoss, denied);
+            pending += denied;
+        }
+        // denied loss increases the cost base which is a more negative basis
+        if pending > N::zero() && holding.position().0 > N::margin() {
+            holding.add_to_basis(-pending);
+            pending = N::zero();
+        }
+        Ok(realized)
+    });
+    result.map(|_| realized_return)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::DateOrder;
+    use crate::lot::AverageCost;
+    use crate::transaction::Transaction;
+    use crate::unrealized::URealized;
+
+    #[test]
+    fn loss_denied_when_repurchased_after_selling_all() {
+        let mut holding = Holding::with_selector(AverageCost);
+        let transactions = [
+            Transaction::from("2020-01-01,buy,100.0,50.0"),
+            Transaction::from("2020-03-01,sell,100.0,40.0"),
+            Transaction::from("2020-03-20,buy,60.0,42.0"),
+        ];
+        let realized = superficial_loss(&mut holding, &transactions).unwrap();
+
+        assert_eq!(realized.len(), 1);
+        assert_eq!(realized[0].realized(), -400.0);
+        assert_eq!(realized[0].disallowed(), 600.0);
+        assert_eq!(
+            realized[0].adjustment().map(|a| a.code()),
+            Some(AdjustmentCode::SuperficialLoss)
+        );
+        assert_eq!(
+            holding.inventory(),
+            [URealized::from("2020-03-20,60.0,-3120.0")]
+        );
+    }
+
+    #[test]
+    fn loss_not_denied_when_substituted_property_sold_in_period() {
+        let mut holding = Holding::with_selector(AverageCost);
+        let transactions = [
+            Transaction::from("2020-01-01,buy,100.0,50.0"),
+            Transaction::from("2020-03-01,sell,100.0,40.0"),
+            Transaction::from("2020-03-10,buy,60.0,42.0"),
+            Transaction::from("2020-03-25,sell,60.0,43.0"),
+        ];
+        let realized = superficial_loss(&mut holding, &transactions).unwrap();
+
+        assert_eq!(realized[0].adjustment(), None);
+        assert_eq!(realized[0].realized(), -1000.0);
+        assert!(holding.inventory().is_empty());
+    }
+
+    #[test]
+    fn acquisition_substitutes_for_one_loss_only() {
+        let mut holding = Holding::with_selector(AverageCost);
+        let transactions = [
+            Transaction::from("2020-01-01,buy,200.0,50.0"),
+            Transaction::from("2020-03-01,sell,50.0,40.0"),
+            Transaction::from("2020-03-05,sell,50.0,40.0"),
+            Transaction::from("2020-03-10,buy,50.0,42.0"),
+        ];
+        let realized = superficial_loss(&mut holding, &transactions).unwrap();
+
+        assert_eq!(realized[0].disallowed(), 500.0);
+        assert_eq!(realized[1].adjustment(), None);
+        assert!(realized[1].realized() < 0.0);
+    }
+
+    #[test]
+    fn batch_sorted_by_date_when_configured() {
+        let mut holding = Holding::builder()
+            .selector(AverageCost)
+            .date_order(DateOrder::SortBatch)
+            .build()
+            .unwrap();
+        let transactions = [
+            Transaction::from("2020-03-20,buy,60.0,42.0"),
+            Transaction::from("2020-01-01,buy,100.0,50.0"),
+            Transaction::from("2020-03-01,sell,100.0,40.0"),
+        ];
+        let realized = holding.extend_transactions_ca(&transactions).unwrap();
+
+        assert_eq!(realized[0].disallowed(), 600.0);
+        assert_eq!(
+            holding.inventory(),
+            [URealized::from("2020-03-20,60.0,-3120.0")]
+        );
+    }
+}
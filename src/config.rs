@@ -0,0 +1,119 @@
+/// Largest number of decimals a `Holding` can round to with `f64`
+pub const MAX_DECIMALS: u32 = 15;
+
+/// How a `Holding` reports inventory taken out with a `Remove` inventory change
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum RemovalTreatment {
+    /// removed at current cost basis and net of zero with no realized returned
+    #[default]
+    Unreported,
+    /// same as `Unreported` but responds with a zero gain realized at cost
+    AtCost,
+    /// realized at the market price, assumed to be in the inventory change data as price or basis
+    AtMarket,
+    /// realized at zero proceeds taking a net loss
+    AtZero,
+}
+
+/// Order a batch of inventory changes is applied in
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum DateOrder {
+    /// batch has to be sorted by date, an earlier change is an `Error::OutOfOrder`
+    #[default]
+    Strict,
+    /// batch is sorted by date before it is applied, with acquisitions (Long/Add) before disposals
+    /// (Short/Remove) on the same date
+    SortBatch,
+}
+
+/// How the basis of a lot is divided when only part of the lot is closed
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum BasisSplit {
+    /// each part is the basis in proportion to its quantity, calculated separately so after many partial
+    /// closes the parts can drift from the basis of the lot
+    Proportional,
+    /// closed part is in proportion to its quantity and the remainder is left with the residual lot, so
+    /// the parts add up to the basis of the lot to the precision of the `Number`
+    #[default]
+    Remainder,
+    /// same as `Remainder` with the closed part rounded to a number of decimals, for example 2 for cents.
+    /// With `Decimal` the cost of all closed parts plus the residual lot is always exactly the basis
+    Rounded(u32),
+}
+
+/// What a `Holding` does with dust, inventory left below the dust threshold after inventory is closed
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum DustTreatment {
+    /// dust is removed and its basis dropped
+    #[default]
+    Discard,
+    /// dust is removed and realized at zero proceeds, writing off its basis as a loss
+    WriteOff,
+    /// dust is kept as inventory
+    Keep,
+    /// dust is removed and its basis is added to the next inventory opened
+    RollForward,
+}
+
+/// How `Accounts` track the lots of an asset held in several accounts
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum LotTracking {
+    /// each account has its own holding of the asset, required for US crypto from 2025
+    #[default]
+    PerAccount,
+    /// the lots of all accounts are pooled in one holding of the asset, only the quantity held by each
+    /// account is kept
+    Universal,
+}
+
+/// Configuration of a `Holding`, set with `Holding::builder`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct HoldingConfig {
+    pub(crate) removal: RemovalTreatment,
+    pub(crate) date_order: DateOrder,
+    pub(crate) price_decimals: u32,
+    pub(crate) basis_split: BasisSplit,
+    pub(crate) dust_threshold: f64,
+    pub(crate) dust: DustTreatment,
+}
+
+impl Default for HoldingConfig {
+    fn default() -> Self {
+        HoldingConfig {
+            removal: RemovalTreatment::default(),
+            date_order: DateOrder::default(),
+            price_decimals: 10,
+            basis_split: BasisSplit::default(),
+            dust_threshold: 0.0,
+            dust: DustTreatment::default(),
+        }
+    }
+}
+
+impl HoldingConfig {
+    pub fn removal(&self) -> RemovalTreatment {
+        self.removal
+    }
+
+    pub fn date_order(&self) -> DateOrder {
+        self.date_order
+    }
+
+    /// Decimals the price of `Holding::position` is rounded to, default is 10
+    pub fn price_decimals(&self) -> u32 {
+        self.price_decimals
+    }
+
+    pub fn basis_split(&self) -> BasisSplit {
+        self.basis_split
+    }
+
+    /// Quantity held below which inventory left after a close is dust, default is 0 for no dust
+    pub fn dust_threshold(&self) -> f64 {
+        self.dust_threshold
+    }
+
+    pub fn dust(&self) -> DustTreatment {
+        self.dust
+    }
+}
@@ -0,0 +1,164 @@
+use crate::fx::Currency;
+use crate::lot::LotRef;
+use chrono::NaiveDate;
+use std::fmt;
+use std::str::FromStr;
+
+/// Errors returned by the fallible functions of the crate instead of panicking
+#[derive(Debug, PartialEq, Clone)]
+pub enum Error {
+    /// text could not be parsed - description of the field and the text
+    Parse(String, String),
+    /// file could not be read - path, description of the error
+    Read(String, String),
+    /// referenced lot is not held in inventory
+    LotNotFound(LotRef),
+    /// referenced lot quantity is less than the quantity closed - lot, lot quantity, quantity closed
+    InsufficientLot(LotRef, f64, f64),
+    /// `LotSelector` returned an index outside of the inventory - index, inventory count
+    LotIndex(usize, usize),
+    /// inventory change and matched inventory don't offset - change quantity, inventory quantity
+    VolumeMismatch(f64, f64),
+    /// inventory change is dated before the last change applied - change date, last date
+    OutOfOrder(NaiveDate, NaiveDate),
+    /// disposal is larger than the holding - date, quantity disposed, quantity held
+    InsufficientHolding(NaiveDate, f64, f64),
+    /// holding configuration options conflict or are invalid - description
+    Config(String),
+    /// nothing to work on where at least one item is needed - description
+    Empty(String),
+    /// index is outside of the history of a `Ledger` - index, history count
+    HistoryIndex(usize, usize),
+    /// quantity transferred is zero or negative - date, quantity
+    TransferQuantity(NaiveDate, f64),
+    /// fee paid in another asset is not a positive quantity at a zero or positive price - date, quantity,
+    /// price
+    AssetFee(NaiveDate, f64, f64),
+    /// transferred inventory is in the opposite direction of the holding it is transferred into - date
+    TransferDirection(NaiveDate),
+    /// pooled inventory can't be allocated to the quantity held by each account - asset, pooled quantity,
+    /// total quantity of the accounts
+    Allocation(String, f64, f64),
+    /// short inventory is held where only long inventory is supported - date
+    ShortHolding(NaiveDate),
+    /// FX table has no rate for the currency on or before the date - currency, date
+    RateNotFound(Currency, NaiveDate),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(field, s) => write!(f, "'{}' is not a valid value for {}", s, field),
+            Error::Read(path, e) => write!(f, "could not read {}: {}", path, e),
+            Error::LotNotFound(lot) => write!(f, "lot {} is not held in inventory", lot),
+            Error::InsufficientLot(lot, held, closed) => write!(
+                f,
+                "lot {} has quantity {} which is insufficient to close {}",
+                lot, held, closed
+            ),
+            Error::LotIndex(index, count) => write!(
+                f,
+                "lot index {} is outside of inventory with {} lots",
+                index, count
+            ),
+            Error::VolumeMismatch(change, inventory) => write!(
+                f,
+                "inventory change quantity {} does not close inventory quantity {}",
+                change, inventory
+            ),
+            Error::OutOfOrder(date, last) => write!(
+                f,
+                "inventory change dated {} is before the last change applied on {}",
+                date, last
+            ),
+            Error::InsufficientHolding(date, disposed, held) => write!(
+                f,
+                "disposal of {} on {} is larger than the holding of {}",
+                disposed, date, held
+            ),
+            Error::Config(s) => write!(f, "invalid holding configuration: {}", s),
+            Error::Empty(s) => write!(f, "there are no {}", s),
+            Error::HistoryIndex(index, count) => write!(
+                f,
+                "history index {} is outside of a ledger with {} inventory changes",
+                index, count
+            ),
+            Error::TransferQuantity(date, quantity) => write!(
+                f,
+                "transfer of {} on {} is not a positive quantity",
+                quantity, date
+            ),
+            Error::AssetFee(date, quantity, price) => write!(
+                f,
+                "fee of {} at {} on {} is not a positive quantity at a zero or positive price",
+                quantity, price, date
+            ),
+            Error::TransferDirection(date) => write!(
+                f,
+                "inventory transferred on {} is in the opposite direction of the holding",
+                date
+            ),
+            Error::Allocation(asset, pooled, held) => write!(
+                f,
+                "pooled {} of {} can't be allocated to accounts holding {}",
+                pooled, asset, held
+            ),
+            Error::ShortHolding(date) => write!(
+                f,
+                "short inventory held on {} is not supported, only long inventory",
+                date
+            ),
+            Error::RateNotFound(currency, date) => {
+                write!(f, "no {} rate on or before {}", currency, date)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// parse a required comma separated field
+pub(crate) fn parse_field<T>(field: &[&str], i: usize, name: &str) -> Result<T, Error>
+where
+    T: FromStr,
+{
+    let s = field.get(i).copied().unwrap_or("");
+    s.trim()
+        .parse()
+        .map_err(|_| Error::Parse(name.to_owned(), s.to_owned()))
+}
+
+// parse a required comma separated date field in the form of `yyyy-mm-dd`
+pub(crate) fn parse_date(field: &[&str], i: usize, name: &str) -> Result<NaiveDate, Error> {
+    let s = field.get(i).copied().unwrap_or("");
+    NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+        .map_err(|_| Error::Parse(name.to_owned(), s.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn parse_fields_with_errors() {
+        let field: Vec<&str> = "2020-01-01,100.0,abc".split(',').collect();
+        assert_eq!(
+            parse_date(&field, 0, "date"),
+            Ok(NaiveDate::from_ymd_opt(2020, 1, 1).unwrap())
+        );
+        assert_eq!(parse_field::<f64>(&field, 1, "quantity"), Ok(100.0));
+        assert_eq!(
+            parse_field::<f64>(&field, 2, "basis"),
+            Err(Error::Parse("basis".to_owned(), "abc".to_owned()))
+        );
+        assert_eq!(
+            parse_field::<f64>(&field, 3, "price"),
+            Err(Error::Parse("price".to_owned(), "".to_owned()))
+        );
+        assert_eq!(
+            Error::Parse("basis".to_owned(), "abc".to_owned()).to_string(),
+            "'abc' is not a valid value for basis"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::error::Error;
+use crate::fx::Currency;
+use crate::holding::Holding;
+use crate::inventory::{Inventory, InventoryType, VolumeSplit};
+use crate::lot::LotRef;
+use crate::num::Number;
+use crate::realized::Realized;
+use crate::transaction::Transaction;
+use chrono::NaiveDate;
+
+/// Inventory change with an extra fee on top of its own `Inventory::fee`, for example the market value
+/// of a fee paid in another asset.
+///
+/// The extra fee is pro-rated by quantity when split.
+// inventory change, extra fee
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct WithFee<T, N = f64>(T, N);
+
+impl<T, N> WithFee<T, N>
+where
+    T: Inventory<N>,
+    N: Number,
+{
+    pub fn new(inv: T, fee: N) -> Self {
+        WithFee(inv, fee)
+    }
+
+    /// Inventory change without the extra fee
+    pub fn inventory(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T, N> Inventory<N> for WithFee<T, N>
+where
+    T: Inventory<N>,
+    N: Number,
+{
+    fn basis(&self) -> N {
+        self.0.basis()
+    }
+
+    fn quantity(&self) -> N {
+        self.0.quantity()
+    }
+
+    fn date(&self) -> NaiveDate {
+        self.0.date()
+    }
+
+    fn itype(&self) -> InventoryType {
+        self.0.itype()
+    }
+
+    fn lot(&self) -> Option<LotRef> {
+        self.0.lot()
+    }
+
+    fn fee(&self) -> N {
+        self.0.fee() + self.1
+    }
+
+    fn currency(&self) -> Option<Currency> {
+        self.0.currency()
+    }
+}
+
+impl<T, N> VolumeSplit<WithFee<T, N>, N> for WithFee<T, N>
+where
+    T: Inventory<N> + VolumeSplit<T, N>,
+    N: Number,
+{
+    fn split(&self, quantity: N) -> (WithFee<T, N>, WithFee<T, N>) {
+        let (split1, split2) = self.0.split(quantity);
+        let fee = if self.1 == N::zero() || self.0.quantity() == N::zero() {
+            N::zero()
+        } else {
+            self.1 * quantity / self.0.quantity().abs()
+        };
+        (WithFee(split1, fee), WithFee(split2, self.1 - fee))
+    }
+}
+
+/// Realized gains of a trade with a fee paid in another asset
+#[derive(Debug, PartialEq, Clone)]
+pub struct AssetFeeRealized<N = f64> {
+    /// realized by the trade, with the fee taken from the proceeds of a sale
+    pub trade: Vec<Realized<N>>,
+    /// realized by disposing of the asset the fee was paid in
+    pub fee: Vec<Realized<N>>,
+}
+
+/// Applies `trade` to `holding` with a fee of `fee_quantity` paid from `fee_holding`, a different asset
+/// such as ETH paying gas for a token trade.
+///
+/// Paying the fee is a disposal of the fee asset at its market value `fee_quantity * fee_price`, which
+/// relieves `fee_holding` and realizes a gain/loss.  The market value is a fee of the trade, added to the
+/// cost of inventory opened or taken from the proceeds of inventory closed.
+///
+/// Both holdings are left unchanged on an error.  `fee_quantity` has to be positive, `fee_price` zero or
+/// positive and the fee holding has to hold at least `fee_quantity` of long inventory.
+pub fn trade_with_asset_fee<T, N>(
+    holding: &mut Holding<N>,
+    trade: &T,
+    fee_holding: &mut Holding<N>,
+    fee_quantity: N,
+    fee_price: N,
+) -> Result<AssetFeeRealized<N>, Error>
+where
+    T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    N: Number,
+{
+    if fee_quantity <= N::zero() || fee_price < N::zero() {
+        return Err(Error::AssetFee(
+            trade.date(),
+            fee_quantity.to_f64(),
+            fee_price.to_f64(),
+        ));
+    }
+    let held = fee_holding.position().0;
+    if held + N::margin() < fee_quantity {
+        return Err(Error::InsufficientHolding(
+            trade.date(),
+            fee_quantity.to_f64(),
+            held.to_f64(),
+        ));
+    }
+    let disposal = Transaction::new(trade.date(), InventoryType::Short, fee_quantity, fee_price);
+    let mut fee_after = fee_holding.clone();
+    let fee = fee_after.try_add_transaction(&disposal)?;
+    let mut after = holding.clone();
+    let trade = after.try_add_transaction(&WithFee(*trade, fee_quantity * fee_price))?;
+    *holding = after;
+    *fee_holding = fee_after;
+    Ok(AssetFeeRealized { trade, fee })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::unrealized::URealized;
+
+    #[test]
+    fn split_pro_rates_extra_fee() {
+        let inv = WithFee::new(Transaction::from("2020-01-01,short,100.0,25.0,,4.0"), 6.0);
+        assert_eq!(inv.fee(), 10.0);
+        let (split1, split2) = inv.split(25.0);
+        assert_eq!(split1.fee(), 2.5);
+        assert_eq!(split2.fee(), 7.5);
+        assert_eq!(split2.inventory().quantity(), -75.0);
+
+        let zero = WithFee::new(Transaction::from("2020-01-01,short,0.0,25.0"), 6.0);
+        assert_eq!(zero.split(0.0).0.fee(), 0.0);
+    }
+
+    #[test]
+    fn fee_not_positive_or_negative_price_is_an_error() {
+        let mut eth = Holding::new(&Transaction::from("2020-01-01,buy,1.0,1000.0"));
+        let mut token = Holding::default();
+        let trade = Transaction::from("2020-02-01,buy,100.0,2.0");
+        let date = trade.date();
+
+        assert_eq!(
+            trade_with_asset_fee(&mut token, &trade, &mut eth, -0.01, 1500.0),
+            Err(Error::AssetFee(date, -0.01, 1500.0))
+        );
+        assert_eq!(
+            trade_with_asset_fee(&mut token, &trade, &mut eth, 0.0, 1500.0),
+            Err(Error::AssetFee(date, 0.0, 1500.0))
+        );
+        assert_eq!(
+            trade_with_asset_fee(&mut token, &trade, &mut eth, 0.01, -1500.0),
+            Err(Error::AssetFee(date, 0.01, -1500.0))
+        );
+        assert!(token.inventory().is_empty());
+        assert_eq!(eth.inventory(), [URealized::from("2020-01-01,1.0,-1000.0")]);
+    }
+
+    #[test]
+    fn insufficient_fee_asset_leaves_holdings_unchanged() {
+        let mut eth = Holding::new(&Transaction::from("2020-01-01,buy,0.005,1000.0"));
+        let mut token = Holding::default();
+        let trade = Transaction::from("2020-02-01,buy,100.0,2.0");
+
+        assert!(matches!(
+            trade_with_asset_fee(&mut token, &trade, &mut eth, 0.01, 1500.0),
+            Err(Error::InsufficientHolding(_, _, _))
+        ));
+        assert!(token.inventory().is_empty());
+        assert_eq!(eth.inventory(), [URealized::from("2020-01-01,0.005,-5.0")]);
+    }
+}
@@ -0,0 +1,361 @@
+use crate::error::{parse_date, parse_field, Error};
+use crate::holding::Holding;
+use crate::inventory::{Inventory, InventoryType, VolumeSplit};
+use crate::num::Number;
+use crate::portfolio::Portfolio;
+use crate::realized::Realized;
+use crate::transaction::Transaction;
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Three letter currency code such as `USD` or `EUR`
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+pub struct Currency([u8; 3]);
+
+impl FromStr for Currency {
+    type Err = Error;
+    /// three ASCII letters in either case, for example `"EUR"` or `"eur"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let code = s.trim().to_ascii_uppercase();
+        match code.as_bytes() {
+            [a, b, c] if code.bytes().all(|letter| letter.is_ascii_uppercase()) => {
+                Ok(Currency([*a, *b, *c]))
+            }
+            _ => Err(Error::Parse("currency".to_owned(), s.to_owned())),
+        }
+    }
+}
+
+impl From<&str> for Currency {
+    /// Same form as `FromStr` but panics if the text can't be parsed
+    fn from(s: &str) -> Self {
+        s.parse().unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // always ASCII letters
+        write!(f, "{}", String::from_utf8_lossy(&self.0))
+    }
+}
+
+/// Table of FX rates to a reporting currency by date.
+///
+/// A rate is the amount of the reporting currency for one unit of the currency.  The rate used on a date
+/// is the latest rate on or before the date, so a table of business days covers weekends and holidays.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FxRates<N = f64> {
+    reporting: Currency,
+    rates: BTreeMap<(Currency, NaiveDate), N>,
+}
+
+/// `Realized` converted to the reporting currency along with the values in the currency traded
+#[derive(Debug, PartialEq, Clone)]
+pub struct FxRealized<N = f64> {
+    /// currency traded
+    pub currency: Currency,
+    /// rate on the open date, applied to the cost
+    pub open_rate: N,
+    /// rate on the close date, applied to the proceeds
+    pub close_rate: N,
+    /// realized in the currency traded
+    pub native: Realized<N>,
+    /// realized in the reporting currency
+    pub realized: Realized<N>,
+}
+
+impl<N> FxRates<N>
+where
+    N: Number,
+{
+    /// Empty table converting to `reporting`
+    pub fn new(reporting: Currency) -> Self {
+        FxRates {
+            reporting,
+            rates: BTreeMap::new(),
+        }
+    }
+
+    /// Table converting to `reporting` from csv text with a `"yyyy-mm-dd,currency,rate"` line per rate,
+    /// for example read from a file.  Blank lines and a first line header starting with `date` are
+    /// skipped.
+    pub fn from_csv(reporting: Currency, csv: &str) -> Result<Self, Error> {
+        let mut fx = FxRates::new(reporting);
+        for (i, line) in csv.lines().enumerate() {
+            if line.trim().is_empty() || (i == 0 && line.trim_start().starts_with("date")) {
+                continue;
+            }
+            let field: Vec<&str> = line.split(',').collect();
+            fx.insert(
+                parse_field(&field, 1, "currency")?,
+                parse_date(&field, 0, "rate date")?,
+                parse_field(&field, 2, "rate")?,
+            );
+        }
+        Ok(fx)
+    }
+
+    /// Same as `from_csv` reading the csv text from the file at `path`
+    pub fn from_csv_path<P>(reporting: Currency, path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let csv = fs::read_to_string(path)
+            .map_err(|e| Error::Read(path.display().to_string(), e.to_string()))?;
+        FxRates::from_csv(reporting, &csv)
+    }
+
+    pub fn reporting(&self) -> Currency {
+        self.reporting
+    }
+
+    /// Adds or replaces the rate of `currency` on `date`
+    pub fn insert(&mut self, currency: Currency, date: NaiveDate, rate: N) {
+        self.rates.insert((currency, date), rate);
+    }
+
+    /// Rate of `currency` on `date`, the latest rate on or before the date.  The rate of the reporting
+    /// currency is always one.
+    pub fn rate(&self, currency: Currency, date: NaiveDate) -> Result<N, Error> {
+        if currency == self.reporting {
+            return Ok(N::one());
+        }
+        self.rates
+            .range((currency, NaiveDate::MIN)..=(currency, date))
+            .next_back()
+            .map(|(_, rate)| *rate)
+            .ok_or(Error::RateNotFound(currency, date))
+    }
+
+    /// Converts `realized` in `currency` to the reporting currency, the cost at the rate of the open date
+    /// and the proceeds at the rate of the close date.
+    ///
+    /// The open date has to be the date the lot was acquired.  That isn't the case for the pooled lot of
+    /// an `AverageCost` holding, which keeps the date of the first acquisition, or for a wash sale
+    /// replacement lot, whose open date is moved earlier, so their cost is converted at the wrong rate.
+    pub fn convert(
+        &self,
+        realized: &[Realized<N>],
+        currency: Currency,
+    ) -> Result<Vec<FxRealized<N>>, Error> {
+        realized
+            .iter()
+            .map(|r| {
+                let open_rate = self.rate(currency, r.open_date())?;
+                let close_rate = self.rate(currency, r.close_date())?;
+                Ok(FxRealized {
+                    currency,
+                    open_rate,
+                    close_rate,
+                    native: r.clone(),
+                    realized: r.converted(open_rate, close_rate),
+                })
+            })
+            .collect()
+    }
+
+    /// Adds an inventory change to `holding` and converts the realized gains from the currency of the
+    /// change, the reporting currency if it has none.
+    ///
+    /// The holding is kept in the currency traded, so all inventory changes of a holding have to be in
+    /// the same currency.  The holding is left unchanged if a rate is missing.  A holding pooling its
+    /// inventory, such as `AverageCost`, returns an error as its lots don't keep the acquisition date, see
+    /// `convert`.
+    pub fn try_add_transaction<T>(
+        &self,
+        holding: &mut Holding<N>,
+        inv: &T,
+    ) -> Result<Vec<FxRealized<N>>, Error>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        if holding.pooled() {
+            return Err(Error::Config(
+                "FX conversion needs the acquisition date of each lot, not a pooled holding"
+                    .to_owned(),
+            ));
+        }
+        let mut after = holding.clone();
+        let realized = after.try_add_transaction(inv)?;
+        let converted = self.convert(&realized, inv.currency().unwrap_or(self.reporting))?;
+        *holding = after;
+        Ok(converted)
+    }
+
+    /// Cash leg of `inv` as an inventory change of its foreign currency, priced in the reporting currency
+    /// at the rate of the date.
+    ///
+    /// Buying spends the cost plus the fee in the currency, a disposal of currency, and selling receives
+    /// the proceeds less the fee, an acquisition of currency.  An inventory change in the reporting
+    /// currency, or an Add/Remove, has no cash leg.
+    pub fn cash_leg<T>(&self, inv: &T) -> Result<Option<Transaction<N>>, Error>
+    where
+        T: Inventory<N>,
+    {
+        let currency = match inv.currency() {
+            Some(c) if c != self.reporting => c,
+            _ => return Ok(None),
+        };
+        if !matches!(inv.itype(), InventoryType::Long | InventoryType::Short) {
+            return Ok(None);
+        }
+        let cash = inv.basis() - inv.fee();
+        let itype = if cash < N::zero() {
+            InventoryType::Short
+        } else {
+            InventoryType::Long
+        };
+        let rate = self.rate(currency, inv.date())?;
+        // priced in the reporting currency so the leg has no currency of its own
+        Ok(Some(Transaction::new(inv.date(), itype, cash.abs(), rate)))
+    }
+
+    /// Adds the cash legs of `invs` to `cash`, a portfolio of the foreign currencies held keyed by
+    /// currency code, and returns the realized FX gains in the reporting currency.
+    ///
+    /// Spending a currency relieves its lots acquired at earlier rates, realizing the FX gain/loss
+    /// against the reporting currency, for example US Section 988 or Canadian foreign exchange gains.
+    /// Spending more of a currency than `cash` holds returns an error instead of opening a short currency
+    /// position.  Inventory changes have to be sorted by date and the cash legs before the one in error
+    /// remain applied.
+    pub fn try_add_cash_legs<T>(
+        &self,
+        cash: &mut Portfolio<N>,
+        invs: &[T],
+    ) -> Result<Vec<Realized<N>>, Error>
+    where
+        T: Inventory<N>,
+    {
+        let mut gains_r = Vec::new();
+        for inv in invs.iter() {
+            if let (Some(leg), Some(currency)) = (self.cash_leg(inv)?, inv.currency()) {
+                let currency = currency.to_string();
+                let held = cash.position(&currency).map_or(N::zero(), |(q, _, _)| q);
+                let spent = -leg.quantity();
+                if spent > N::zero() && held + N::margin() < spent {
+                    return Err(Error::InsufficientHolding(
+                        leg.date(),
+                        spent.to_f64(),
+                        held.to_f64(),
+                    ));
+                }
+                gains_r.extend(cash.try_add_transaction(&currency, &leg)?);
+            }
+        }
+        Ok(gains_r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn set_rates() -> FxRates {
+        FxRates::from_csv(
+            Currency::from("USD"),
+            "date,currency,rate\n2020-01-01,EUR,1.25\n\n2020-06-01,EUR,1.5\n2020-01-01,GBP,1.75\n",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn cash_leg_spends_cost_and_receives_proceeds_net_of_fee() {
+        let fx = set_rates();
+        let buy = Transaction::from("2020-01-02,buy,10.0,100.0,,5.0,EUR");
+        assert_eq!(
+            fx.cash_leg(&buy),
+            Ok(Some(Transaction::from("2020-01-02,sell,1005.0,1.25")))
+        );
+        let sell = Transaction::from("2020-06-15,sell,10.0,100.0,,5.0,eur");
+        assert_eq!(
+            fx.cash_leg(&sell),
+            Ok(Some(Transaction::from("2020-06-15,buy,995.0,1.5")))
+        );
+        assert_eq!(
+            fx.cash_leg(&Transaction::from("2020-01-02,buy,10.0,100.0")),
+            Ok(None)
+        );
+        assert_eq!(
+            fx.cash_leg(&Transaction::from("2020-01-02,Add,10.0,100.0,,,EUR")),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn pooled_holding_is_not_converted() {
+        let fx = set_rates();
+        let mut holding = Holding::with_selector(crate::lot::AverageCost);
+        let buy = Transaction::from("2020-01-02,buy,10.0,100.0,,,EUR");
+        assert!(matches!(
+            fx.try_add_transaction(&mut holding, &buy),
+            Err(Error::Config(_))
+        ));
+        assert!(holding.inventory().is_empty());
+    }
+
+    #[test]
+    fn rates_read_from_csv_file() {
+        let path = std::env::temp_dir().join(format!("costbasis-fx-{}.csv", std::process::id()));
+        fs::write(&path, "date,currency,rate\n2020-01-01,EUR,1.25\n").unwrap();
+        let fx: Result<FxRates, Error> = FxRates::from_csv_path(Currency::from("USD"), &path);
+        fs::remove_file(&path).unwrap();
+        let date = NaiveDate::from_ymd_opt(2020, 1, 2).unwrap();
+        assert_eq!(fx.unwrap().rate(Currency::from("EUR"), date), Ok(1.25));
+        assert!(matches!(
+            FxRates::<f64>::from_csv_path(Currency::from("USD"), &path),
+            Err(Error::Read(_, _))
+        ));
+    }
+
+    #[test]
+    fn currency_code_is_three_letters() {
+        assert_eq!(Currency::from("eur"), Currency::from("EUR"));
+        assert_eq!(Currency::from("CAD").to_string(), "CAD");
+        assert!("EURO".parse::<Currency>().is_err());
+        assert!("E1R".parse::<Currency>().is_err());
+    }
+
+    #[test]
+    fn rate_is_latest_on_or_before_date() {
+        let fx = set_rates();
+        let eur = Currency::from("EUR");
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        assert_eq!(fx.rate(eur, date("2020-01-01")), Ok(1.25));
+        assert_eq!(fx.rate(eur, date("2020-05-31")), Ok(1.25));
+        assert_eq!(fx.rate(eur, date("2020-07-04")), Ok(1.5));
+        assert_eq!(fx.rate(Currency::from("USD"), date("2019-01-01")), Ok(1.0));
+        assert_eq!(
+            fx.rate(eur, date("2019-12-31")),
+            Err(Error::RateNotFound(eur, date("2019-12-31")))
+        );
+    }
+
+    #[test]
+    fn convert_cost_at_open_rate_and_proceeds_at_close_rate() {
+        let fx = set_rates();
+        let native = Realized::from("2020-06-15,-10.0,1000.0,2020-01-02,-1000.0");
+        let converted = fx
+            .convert(std::slice::from_ref(&native), Currency::from("EUR"))
+            .unwrap();
+        assert_eq!(
+            converted,
+            [FxRealized {
+                currency: Currency::from("EUR"),
+                open_rate: 1.25,
+                close_rate: 1.5,
+                native,
+                realized: Realized::from("2020-06-15,-10.0,1500.0,2020-01-02,-1250.0"),
+            }]
+        );
+        assert!(fx
+            .convert(&[converted[0].native.clone()], Currency::from("CAD"))
+            .is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::error::Error;
+use chrono::{Months, NaiveDate};
+
+/// Classification of a realized gain by how long the inventory was held
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum GainType {
+    Shortterm,
+    Longterm,
+}
+
+impl std::str::FromStr for GainType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Short" | "Shortterm" | "st" | "short" => Ok(GainType::Shortterm),
+            "Long" | "Longterm" | "lt" | "long" => Ok(GainType::Longterm),
+            _ => Err(Error::Parse("GainType".to_owned(), s.to_owned())),
+        }
+    }
+}
+
+/// Threshold in months a holding has to be held past to be long-term.
+///
+/// A gain is long-term when the close date is after the open date plus the threshold.  Default is the
+/// US rule of one year, so inventory sold one year and a day after it was opened is long-term.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct HoldingPeriod(u32);
+
+impl Default for HoldingPeriod {
+    fn default() -> Self {
+        HoldingPeriod(12)
+    }
+}
+
+impl HoldingPeriod {
+    pub fn months(months: u32) -> Self {
+        HoldingPeriod(months)
+    }
+
+    /// Classify inventory opened on `open` and closed on `close`
+    pub fn gain_type(&self, open: NaiveDate, close: NaiveDate) -> GainType {
+        match open.checked_add_months(Months::new(self.0)) {
+            Some(long_term) if close > long_term => GainType::Longterm,
+            _ => GainType::Shortterm,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn one_year_and_a_day_is_long_term() {
+        let period = HoldingPeriod::default();
+        let open = date("2020-03-15");
+        assert_eq!(
+            period.gain_type(open, date("2021-03-15")),
+            GainType::Shortterm
+        );
+        assert_eq!(
+            period.gain_type(open, date("2021-03-16")),
+            GainType::Longterm
+        );
+        // leap day anniversary
+        assert_eq!(
+            period.gain_type(date("2020-02-29"), date("2021-03-01")),
+            GainType::Longterm
+        );
+    }
+
+    #[test]
+    fn configurable_threshold() {
+        let period = HoldingPeriod::months(6);
+        let open = date("2020-01-31");
+        assert_eq!(
+            period.gain_type(open, date("2020-07-31")),
+            GainType::Shortterm
+        );
+        assert_eq!(
+            period.gain_type(open, date("2020-08-01")),
+            GainType::Longterm
+        );
+    }
+
+    #[test]
+    fn parse_gain_type() {
+        assert_eq!("lt".parse(), Ok(GainType::Longterm));
+        assert_eq!("Short".parse(), Ok(GainType::Shortterm));
+        assert!("medium".parse::<GainType>().is_err());
+    }
+}
@@ -1,9 +1,17 @@
+use crate::config::{
+    BasisSplit, DateOrder, DustTreatment, HoldingConfig, RemovalTreatment, MAX_DECIMALS,
+};
+use crate::error::Error;
+use crate::fee::AssetFeeRealized;
 use crate::inventory::{Inventory, InventoryType, VolumeSplit};
+use crate::lot::{Fifo, LotRef, LotSelector};
+use crate::num::Number;
 use crate::realized::Realized;
 use crate::unrealized::URealized;
-use crate::MARGIN_ERROR_QUANTITY;
-use std::collections::HashSet;
+use crate::{ca, fee, uk, wash};
+use chrono::NaiveDate;
 use std::fmt;
+use std::sync::Arc;
 
 /// Holding contains a set of `URealized` inventory.
 ///
@@ -16,31 +24,67 @@ use std::fmt;
 /// and is treated the same as a Long Transaction
 ///
 /// Removing inventory such as a Withdraw of stock or Sending Crypto has a few options
-/// when treating if it creates gain or not
+/// when treating if it creates gain or not, set with a `RemovalTreatment`
 ///
-/// - Default => removal of inventory is removed at current cost basis and net of zero with no realized returned
-/// - `ADD_REALIZED_FOR_REMOVED` => same as default but will respond with zero gain realized
-/// - `REMOVED_VALUE_AT_MARKET` => assumes market price is in inventory change data as price or basis
-/// - `REMOVED_VALUE_AT_ZERO` => force value at zero proceeds taking a net loss
+/// - `Unreported` => default, removal of inventory is removed at current cost basis and net of zero with no realized returned
+/// - `AtCost` => same as default but will respond with zero gain realized
+/// - `AtMarket` => assumes market price is in inventory change data as price or basis
+/// - `AtZero` => force value at zero proceeds taking a net loss
 ///
-/// All inventory is treated as FIFO
+/// Use `Holding::builder` to configure a holding.
 ///
-/// Future options LIFO, Avg Price, HIFO?
+/// Inventory changes that reference a specific lot with `Inventory::lot` relieve only that lot.  Otherwise
+/// which inventory is relieved is decided by a `LotSelector`.  Inventory is treated as FIFO by default,
+/// use `Holding::with_selector` for LIFO, HIFO, LOFO, average cost or a user defined selection.
 ///
-#[derive(Debug, Default)]
-pub struct Holding {
-    unrealized: Vec<URealized>,
+/// Inventory left after a close that is below the dust threshold is handled following the
+/// `DustTreatment` of the holding and recorded as a `DustEvent`.
+///
+/// Quantity and basis are `f64` unless another `Number` is given, for example `Holding<Decimal>`.
+///
+#[derive(Debug, Clone)]
+pub struct Holding<N = f64>
+where
+    N: Number,
+{
+    unrealized: Vec<URealized<N>>,
     direction: Option<InventoryType>,
-    config: HashSet<String>,
+    config: HoldingConfig,
+    selector: Arc<dyn LotSelector<N>>,
+    last_date: Option<NaiveDate>,
+    dust: Vec<DustEvent<N>>,
+    // basis of dust rolled forward into the next inventory opened
+    dust_basis: N,
+}
+
+/// Dust left in a holding after an inventory change and how it was treated
+#[derive(Debug, PartialEq, Clone)]
+pub struct DustEvent<N = f64> {
+    pub date: NaiveDate,
+    pub quantity: N,
+    pub basis: N,
+    pub treatment: DustTreatment,
+}
+
+impl<N> Default for Holding<N>
+where
+    N: Number,
+{
+    fn default() -> Self {
+        Holding::with_selector(Fifo)
+    }
 }
 
-impl From<&[URealized]> for Holding {
-    fn from(g: &[URealized]) -> Self {
+impl<N> From<&[URealized<N>]> for Holding<N>
+where
+    N: Number,
+{
+    fn from(g: &[URealized<N>]) -> Self {
         let mut gains = Holding::default();
         gains.unrealized.extend(g);
         // sum to determine direction
-        let quantity: f64 = g.iter().map(|u| u.quantity()).sum();
-        if quantity > 0.0 {
+        let quantity: N = g.iter().map(|u| u.quantity()).sum();
+        if quantity > N::zero() {
             gains.direction = Some(InventoryType::Long);
         } else {
             gains.direction = Some(InventoryType::Short);
@@ -49,96 +93,429 @@ impl From<&[URealized]> for Holding {
     }
 }
 
-impl Holding {
+impl<N> Holding<N>
+where
+    N: Number,
+{
     pub fn new<T>(inv: &T) -> Self
     where
-        T: Inventory + VolumeSplit<T> + Copy,
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
     {
         let mut gains = Holding::default();
         gains.add_transaction(inv);
         gains
     }
 
-    // ASSUMES FIFO FOR NOW -> potential options for LIFO or LOTS or Avg Cost
+    /// Empty holding that relieves inventory in the order chosen by `selector`
+    pub fn with_selector<S>(selector: S) -> Self
+    where
+        S: LotSelector<N> + 'static,
+    {
+        Holding {
+            unrealized: Vec::new(),
+            direction: None,
+            config: HoldingConfig::default(),
+            selector: Arc::new(selector),
+            last_date: None,
+            dust: Vec::new(),
+            dust_basis: N::zero(),
+        }
+    }
+
+    /// Builder to configure a holding
+    pub fn builder() -> HoldingBuilder<N> {
+        HoldingBuilder::default()
+    }
+
+    /// Configuration of the holding
+    pub fn config(&self) -> &HoldingConfig {
+        &self.config
+    }
+
+    // true when inventory is pooled into a single lot by the lot selector
+    pub(crate) fn pooled(&self) -> bool {
+        self.selector.pooled()
+    }
+
     /// Transaction is an inventory change of Add/Deposit/Receive, Remove/Use/Send, Buy/Long, Short/Sell
     /// Transactions/Inventory Change must be sorted by date
-    pub fn add_transaction<T>(&mut self, inv: &T) -> Vec<Realized>
+    ///
+    /// Panics if the inventory change is dated before the last change applied or can't be matched to
+    /// inventory, for example a referenced lot that isn't held.  Use `try_add_transaction` to handle the
+    /// error instead.
+    pub fn add_transaction<T>(&mut self, inv: &T) -> Vec<Realized<N>>
     where
-        T: Inventory + VolumeSplit<T> + Copy,
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        match self.try_add_transaction(inv) {
+            Ok(realized) => realized,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Same as `add_transaction` but returns an `Error` when the inventory change can't be matched.
+    ///
+    /// Inventory is left unchanged on an error, including an error matching a later part of a change
+    /// that relieves more than one lot.
+    pub fn try_add_transaction<T>(&mut self, inv: &T) -> Result<Vec<Realized<N>>, Error>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        if let Some(last) = self.last_date {
+            if inv.date() < last {
+                return Err(Error::OutOfOrder(inv.date(), last));
+            }
+        }
+        let relieved = self.direction.filter(|_| !self.match_direction(inv));
+        // lots relieved before an error in a later part of the change are restored
+        let before = relieved.map(|_| (self.unrealized.clone(), self.dust_basis));
+        let mut realized = match self.apply_transaction(inv) {
+            Ok(realized) => realized,
+            Err(e) => {
+                if let Some((unrealized, dust_basis)) = before {
+                    self.unrealized = unrealized;
+                    self.dust_basis = dust_basis;
+                    self.direction = relieved;
+                }
+                return Err(e);
+            }
+        };
+        // only inventory left of the lots relieved is dust, not a position opened by the change
+        if relieved.is_some() && self.direction == relieved {
+            realized.extend(self.check_dust(inv.date()));
+        }
+        self.last_date = Some(inv.date());
+        Ok(realized)
+    }
+
+    fn apply_transaction<T>(&mut self, inv: &T) -> Result<Vec<Realized<N>>, Error>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
     {
         // same direction of inventory change or empty inventory - add to inventory and exit with zero realized
         //if self.unrealized.is_empty() || self.direction == Some(inv.itype()) {
         if self.match_direction(inv) {
             self.add_inventory(inv.into());
-            vec![]
+            Ok(vec![])
         // change is in opposite direction which will remove inventory and create gains
         } else {
+            // lot to relieve is picked once so the split and the close act on the same inventory
+            let lot = match inv.lot() {
+                Some(lot_ref) => self.find_lot(&lot_ref, inv)?,
+                None => self.next_lot(inv)?,
+            };
             // split_matches creating equal pairs between inv change and unrealized inventory
-            let split_inv = self.split_matching_first(*inv);
+            let split_inv = self.split_matching_lot(lot, *inv);
             // create realized and remove matches as long as there is inventory
-            let mut realized_return = vec![self.match_close(&split_inv[0])];
+            let mut realized_return = vec![self.match_close(lot, &split_inv[0])?];
             // continue if there is more inventory change
             if split_inv.len() > 1 {
-                realized_return.extend(self.add_transaction(&split_inv[1]));
+                realized_return.extend(self.apply_transaction(&split_inv[1])?);
             }
             // Remove is handled differently depending on Configuration
             if inv.itype() == InventoryType::Remove {
-                self.mod_removed(realized_return)
+                Ok(self.mod_removed(realized_return))
             } else {
-                realized_return
+                Ok(realized_return)
             }
         }
     }
-    /// Adds a slice or series of inventory changes.  The series must be sorted by date.
-    pub fn extend_transactions<T>(&mut self, invs: &[T]) -> Vec<Realized>
+    /// Adds a slice or series of inventory changes.  The series must be sorted by date unless the
+    /// holding is configured with `DateOrder::SortBatch`.
+    ///
+    /// Panics the same as `add_transaction`.
+    pub fn extend_transactions<T>(&mut self, invs: &[T]) -> Vec<Realized<N>>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        match self.try_extend_transactions(invs) {
+            Ok(realized) => realized,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Same as `extend_transactions` but returns the first `Error` instead of panicking.
+    ///
+    /// Inventory changes before the one in error remain applied to the holding.
+    pub fn try_extend_transactions<T>(&mut self, invs: &[T]) -> Result<Vec<Realized<N>>, Error>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        let (gains_r, result) =
+            self.extend_with(invs, |holding, inv| holding.try_add_transaction(inv));
+        result.map(|_| gains_r)
+    }
+
+    // adds a batch, sorted first if configured, one change at a time with `add` up to the first error
+    // returns the realized gains of the changes applied before the error along with the error
+    pub(crate) fn extend_with<T, F>(
+        &mut self,
+        invs: &[T],
+        mut add: F,
+    ) -> (Vec<Realized<N>>, Result<(), Error>)
     where
-        T: Inventory + VolumeSplit<T> + Copy,
+        T: Inventory<N> + Copy,
+        F: FnMut(&mut Holding<N>, &T) -> Result<Vec<Realized<N>>, Error>,
     {
+        let mut batch = invs.to_vec();
+        if self.config.date_order == DateOrder::SortBatch {
+            sort_batch(&mut batch);
+        }
         // add transactions one by one to keep any realized gains created
-        let mut gains_r: Vec<Realized> = Vec::new();
-        for inv in invs {
-            gains_r.extend(self.add_transaction(inv));
+        let mut gains_r: Vec<Realized<N>> = Vec::new();
+        for inv in batch.iter() {
+            match add(self, inv) {
+                Ok(r) => gains_r.extend(r),
+                Err(e) => return (gains_r, Err(e)),
+            }
+        }
+        (gains_r, Ok(()))
+    }
+
+    /// Adds a batch of inventory changes following the UK share matching rules instead of the lot selector.
+    ///
+    /// The long inventory held is the Section 104 pool before the batch and is left as a single pooled lot.
+    /// Needs the whole batch to look ahead 30 days for the bed and breakfast rule.  See `uk::share_match`.
+    ///
+    /// Only long inventory is matched, a holding with short inventory returns an error.  The share
+    /// matching rules replace the configuration of the holding, so the removal treatment, sorting a batch
+    /// by date and dust handling don't apply and the batch has to be in date order.
+    pub fn extend_transactions_uk<T>(&mut self, invs: &[T]) -> Result<Vec<Realized<N>>, Error>
+    where
+        T: Inventory<N>,
+    {
+        let first = match invs.first() {
+            Some(first) => first.date(),
+            None => return Ok(Vec::new()),
+        };
+        if let Some(last) = self.last_date {
+            if first < last {
+                return Err(Error::OutOfOrder(first, last));
+            }
+        }
+        if self.direction == Some(InventoryType::Short) {
+            return Err(Error::ShortHolding(first));
+        }
+        let (quantity, _, basis) = self.position();
+        let pool = match self.unrealized.first() {
+            Some(first) if quantity > N::margin() => {
+                Some(URealized::new(first.date(), quantity, basis))
+            }
+            _ => None,
+        };
+        let (realized, pool) = uk::share_match(pool, invs)?;
+        self.unrealized = pool.into_iter().collect();
+        self.direction = self.unrealized.first().map(|_| InventoryType::Long);
+        if let Some(last) = invs.last() {
+            self.last_date = Some(last.date());
+        }
+        Ok(realized)
+    }
+
+    /// Adds a batch of inventory changes applying the Canadian superficial loss rule.
+    ///
+    /// Use with an `AverageCost` holding so the denied loss is added to the adjusted cost base of the pool.
+    /// Needs the whole batch to look ahead 30 days and sorts it the same as `try_extend_transactions`.  See
+    /// `ca::superficial_loss`.
+    pub fn extend_transactions_ca<T>(&mut self, invs: &[T]) -> Result<Vec<Realized<N>>, Error>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        ca::superficial_loss(self, invs)
+    }
+
+    // add amount to the basis of the most recent lot, false if there is no inventory
+    pub(crate) fn add_to_basis(&mut self, amount: N) -> bool {
+        match self.unrealized.last_mut() {
+            Some(lot) => {
+                *lot = lot.adjusted(lot.date(), lot.basis() + amount);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds an inventory change with a fee of `fee_quantity` paid in another asset held by `fee_holding`
+    /// at the market price `fee_price`.
+    ///
+    /// See `fee::trade_with_asset_fee`.
+    pub fn try_add_transaction_with_asset_fee<T>(
+        &mut self,
+        inv: &T,
+        fee_holding: &mut Holding<N>,
+        fee_quantity: N,
+        fee_price: N,
+    ) -> Result<AssetFeeRealized<N>, Error>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        fee::trade_with_asset_fee(self, inv, fee_holding, fee_quantity, fee_price)
+    }
+
+    /// Takes `quantity` of inventory out of the holding to transfer to another holding without realizing
+    /// a gain/loss.
+    ///
+    /// Lots are relieved in the order of the lot selector, or from the referenced `lot`, and returned with
+    /// their original open date and basis.  A lot that is only partly transferred is split following the
+    /// basis split.  `quantity` has to be positive.  Inventory is left unchanged on an error.
+    pub fn transfer_out(
+        &mut self,
+        date: NaiveDate,
+        quantity: N,
+        lot: Option<LotRef>,
+    ) -> Result<Vec<URealized<N>>, Error> {
+        if quantity <= N::zero() {
+            return Err(Error::TransferQuantity(date, quantity.to_f64()));
+        }
+        if let Some(last) = self.last_date {
+            if date < last {
+                return Err(Error::OutOfOrder(date, last));
+            }
+        }
+        let held = self.position().0.abs();
+        if held + N::margin() < quantity {
+            return Err(Error::InsufficientHolding(
+                date,
+                quantity.to_f64(),
+                held.to_f64(),
+            ));
+        }
+        let mut holding = self.clone();
+        let mut lots = Vec::new();
+        let mut left = quantity;
+        while left > N::margin() {
+            // opposite direction of the inventory held to pick the lot relieved
+            let close = match holding.direction {
+                Some(InventoryType::Short) => URealized::new(date, left, N::zero()),
+                _ => URealized::new(date, -left, N::zero()),
+            };
+            let i = match lot {
+                Some(lot_ref) => holding.find_lot(&lot_ref, &close)?,
+                None => holding.next_lot(&close)?,
+            };
+            let ur = holding.unrealized[i];
+            if ur.quantity().abs() > left + N::margin() {
+                let (moved, rest) = ur.split_basis(left, holding.config.basis_split);
+                holding.unrealized[i] = rest;
+                lots.push(moved);
+                left = N::zero();
+            } else {
+                holding.unrealized.remove(i);
+                left -= ur.quantity().abs();
+                lots.push(ur);
+            }
+        }
+        holding.check_zero_reset();
+        holding.last_date = Some(date);
+        *self = holding;
+        Ok(lots)
+    }
+
+    /// Puts `lots` transferred out of another holding into inventory keeping their open date and basis.
+    ///
+    /// Lots are kept in order of open date so the lot selector relieves them the same as in the holding
+    /// they came from.  Lots in the opposite direction of the holding are an `Error::TransferDirection`
+    /// and inventory is left unchanged on an error.
+    pub fn transfer_in(&mut self, date: NaiveDate, lots: &[URealized<N>]) -> Result<(), Error> {
+        if let Some(last) = self.last_date {
+            if date < last {
+                return Err(Error::OutOfOrder(date, last));
+            }
+        }
+        let direction = self.direction.or_else(|| lots.first().map(|l| l.itype()));
+        if lots.iter().any(|l| Some(l.itype()) != direction) {
+            return Err(Error::TransferDirection(date));
+        }
+        for lot in lots.iter() {
+            if self.selector.pooled() {
+                self.add_inventory(*lot);
+            } else {
+                let i = self
+                    .unrealized
+                    .partition_point(|ur| ur.date() <= lot.date());
+                self.unrealized.insert(i, *lot);
+                self.direction = direction;
+            }
         }
-        gains_r
+        self.last_date = Some(date);
+        Ok(())
+    }
+
+    /// Applies the US wash sale rule to `realized` from this holding using the lots held as replacements.
+    ///
+    /// See `wash::apply_wash_sales`.
+    pub fn apply_wash_sales(&mut self, realized: &mut [Realized<N>]) {
+        wash::apply_wash_sales(realized, &mut self.unrealized, self.config.basis_split);
+    }
+
+    /// Every dust left after a close and how it was treated, in the order it happened
+    pub fn dust_events(&self) -> &[DustEvent<N>] {
+        &self.dust
+    }
+
+    /// Date of the last inventory change applied to the holding
+    pub fn last_date(&self) -> Option<NaiveDate> {
+        self.last_date
     }
 
-    fn mod_removed(&self, mut realized: Vec<Realized>) -> Vec<Realized> {
-        if self.config.contains("REALIZED_REMOVED_VALUE_AT_COST") {
-            // shows removed realized at cost basis and zero gains
-            realized.iter_mut().for_each(|r| r.zero_profit());
-            realized
-        } else if self.config.contains("REMOVED_VALUE_AT_MARKET") {
+    fn mod_removed(&self, mut realized: Vec<Realized<N>>) -> Vec<Realized<N>> {
+        match self.config.removal {
+            RemovalTreatment::AtCost => {
+                // shows removed realized at cost basis and zero gains
+                realized.iter_mut().for_each(|r| r.zero_profit());
+                realized
+            }
             // assumes market price is in inventory change data as price or basis
-            realized
-        } else if self.config.contains("REMOVED_VALUE_AT_ZERO") {
-            // Force proceeds at zero value
-            realized.iter_mut().for_each(|r| r.zero_value());
-            realized
-        } else {
-            vec![]
+            RemovalTreatment::AtMarket => realized,
+            RemovalTreatment::AtZero => {
+                // Force proceeds at zero value
+                realized.iter_mut().for_each(|r| r.zero_value());
+                realized
+            }
+            RemovalTreatment::Unreported => vec![],
         }
     }
 
-    fn add_inventory(&mut self, ur: URealized) {
+    fn add_inventory(&mut self, mut ur: URealized<N>) {
         if self.direction.is_none() {
             self.direction = Some(ur.itype());
         }
-        self.unrealized.push(ur);
+        if self.dust_basis != N::zero() {
+            ur = ur.adjusted(ur.date(), ur.basis() + self.dust_basis);
+            self.dust_basis = N::zero();
+        }
+        if self.selector.pooled() && !self.unrealized.is_empty() {
+            // average cost keeps one lot with the open date of the pool
+            let pool = self.unrealized[0];
+            self.unrealized[0] = URealized::new(
+                pool.date(),
+                pool.quantity() + ur.quantity(),
+                pool.basis() + ur.basis(),
+            );
+        } else {
+            self.unrealized.push(ur);
+        }
     }
 
-    fn match_close<T>(&mut self, inv: &T) -> Realized
+    fn match_close<T>(&mut self, lot: usize, inv: &T) -> Result<Realized<N>, Error>
     where
-        T: Inventory,
+        T: Inventory<N>,
     {
-        // can create panic if volumes don't match
-        let unrealized = self.unrealized.remove(0);
+        // volumes have to offset after splitting
+        if (inv.quantity() + self.unrealized[lot].quantity()).abs() > N::margin() {
+            return Err(Error::VolumeMismatch(
+                inv.quantity().to_f64(),
+                self.unrealized[lot].quantity().to_f64(),
+            ));
+        }
+        let unrealized = self.unrealized.remove(lot);
         self.check_zero_reset();
-        Realized::match_close(inv, &unrealized)
+        Ok(Realized::match_close(inv, &unrealized))
     }
 
     fn match_direction<T>(&self, inv: &T) -> bool
     where
-        T: Inventory,
+        T: Inventory<N>,
     {
         self.direction.is_none() || self.direction == Some(inv.direction_type())
     }
@@ -149,67 +526,339 @@ impl Holding {
     }
 
     /// Return current inventory
-    pub fn inventory(&self) -> Vec<URealized> {
+    pub fn inventory(&self) -> Vec<URealized<N>> {
         self.unrealized.clone()
     }
 
     /// Current positon of holding: `(quantity, price, basis)`
-    pub fn position(&self) -> (f64, f64, f64) {
+    pub fn position(&self) -> (N, N, N) {
         // return quantity, price per unit, total basis
-        let mut q = 0.0;
-        let mut b = 0.0;
-        let mut p = 0.0;
+        let mut q = N::zero();
+        let mut b = N::zero();
+        let mut p = N::zero();
 
         for ur in self.unrealized.iter() {
             q += ur.quantity();
             b += ur.basis();
         }
 
-        if q.abs() > MARGIN_ERROR_QUANTITY {
-            // round to 10 decimal - TODO: can i link this to margin _error_quanity as inverse
-            p = (-b / q * 10000000000.0).round() / 10000000000.0;
+        if q.abs() > N::margin() {
+            p = (-b / q).round_dp(self.config.price_decimals);
         }
         (q, p, b)
     }
 
+    // inventory netting to zero is reset, unless there is a dust threshold to treat and record it
     fn check_zero_reset(&mut self) {
-        if self.unrealized.is_empty() || self.position().0.abs() < MARGIN_ERROR_QUANTITY {
+        let dust = self.config.dust_threshold > 0.0;
+        if self.unrealized.is_empty() || (!dust && self.position().0.abs() < N::margin()) {
             self.direction = None;
             self.unrealized = vec![];
         }
     }
 
-    // builds first match between inv changes and inventory unrealized
+    // inventory left below the dust threshold is treated following the configuration and recorded
+    fn check_dust(&mut self, date: NaiveDate) -> Vec<Realized<N>> {
+        let (quantity, _, basis) = self.position();
+        if self.unrealized.is_empty() || quantity.abs() >= N::from_f64(self.config.dust_threshold) {
+            return vec![];
+        }
+        self.dust.push(DustEvent {
+            date,
+            quantity,
+            basis,
+            treatment: self.config.dust,
+        });
+        match self.config.dust {
+            DustTreatment::Keep => return vec![],
+            DustTreatment::RollForward => self.dust_basis += basis,
+            _ => (),
+        }
+        let dust = std::mem::take(&mut self.unrealized);
+        self.direction = None;
+        if self.config.dust == DustTreatment::WriteOff {
+            // closed at zero proceeds
+            dust.iter()
+                .map(|lot| {
+                    Realized::match_close(&URealized::new(date, -lot.quantity(), N::zero()), lot)
+                })
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    // index of the inventory relieved next by an opposite direction change
+    fn next_lot<T>(&self, inv: &T) -> Result<usize, Error>
+    where
+        T: Inventory<N>,
+    {
+        let lot = self.selector.select(&self.unrealized, &inv.into());
+        if lot < self.unrealized.len() {
+            Ok(lot)
+        } else {
+            Err(Error::LotIndex(lot, self.unrealized.len()))
+        }
+    }
+
+    // index of the specific lot referenced by an opposite direction change
+    // the lot must hold at least the quantity of the change
+    fn find_lot<T>(&self, lot_ref: &LotRef, inv: &T) -> Result<usize, Error>
+    where
+        T: Inventory<N>,
+    {
+        let lot = self
+            .unrealized
+            .iter()
+            .position(|ur| lot_ref.matches(ur))
+            .ok_or(Error::LotNotFound(*lot_ref))?;
+        if self.unrealized[lot].quantity().abs() + N::margin() < inv.quantity().abs() {
+            return Err(Error::InsufficientLot(
+                *lot_ref,
+                self.unrealized[lot].quantity().abs().to_f64(),
+                inv.quantity().abs().to_f64(),
+            ));
+        }
+        Ok(lot)
+    }
+
+    // builds match between inv changes and the inventory unrealized at index lot
     // quantity has to be in opposite directions between inv and unrealized
     // remaining inv changes returned or inventory modified to match
-    fn split_matching_first<T>(&mut self, inv: T) -> Vec<T>
+    // when inventory is split, the closing portion stays at index lot
+    fn split_matching_lot<T>(&mut self, lot: usize, inv: T) -> Vec<T>
     where
-        T: Inventory + VolumeSplit<T> + Clone,
+        T: Inventory<N> + VolumeSplit<T, N> + Clone,
     {
-        if (inv.quantity() + self.unrealized[0].quantity()).abs() < MARGIN_ERROR_QUANTITY {
+        if (inv.quantity() + self.unrealized[lot].quantity()).abs() < N::margin() {
             vec![inv]
-        } else if self.unrealized[0].quantity().abs() > inv.quantity().abs() {
-            // split first inventory into two
-            let (close_ur, modified_inv) = self.unrealized[0].split(inv.quantity().abs());
-            self.unrealized.remove(0);
-            self.unrealized.insert(0, modified_inv);
-            self.unrealized.insert(0, close_ur);
+        } else if self.unrealized[lot].quantity().abs() > inv.quantity().abs() {
+            // split inventory into two
+            let (close_ur, modified_inv) =
+                self.unrealized[lot].split_basis(inv.quantity().abs(), self.config.basis_split);
+            self.unrealized.remove(lot);
+            self.unrealized.insert(lot, modified_inv);
+            self.unrealized.insert(lot, close_ur);
             vec![inv]
         } else {
-            let (match_trans, remaining_trans) = inv.split(self.unrealized[0].quantity().abs());
+            let (match_trans, remaining_trans) = inv.split(self.unrealized[lot].quantity().abs());
             vec![match_trans, remaining_trans]
         }
     }
+}
 
-    /// Add configuration to holding.
-    ///
-    /// Only options are `ADD_REALIZED_FOR_REMOVED`, `REMOVED_VALUE_AT_MARKET`, and `REMOVED_VALUE_AT_ZERO`
-    pub fn add_config(&mut self, c: &str) {
-        self.config.insert(c.to_owned());
+/// Builder for a configured `Holding`.
+///
+/// Each option can only be set once.  Setting an option again with a different value is a conflict and
+/// `build` returns an `Error::Config` instead of silently keeping one of them.
+///
+/// ```
+/// use costbasis::config::{DateOrder, RemovalTreatment};
+/// use costbasis::holding::Holding;
+/// use costbasis::lot::Lifo;
+///
+/// let holding: Holding = Holding::builder()
+///     .selector(Lifo)
+///     .removal(RemovalTreatment::AtCost)
+///     .date_order(DateOrder::SortBatch)
+///     .build()
+///     .unwrap();
+/// assert_eq!(holding.config().removal(), RemovalTreatment::AtCost);
+/// ```
+#[derive(Debug)]
+pub struct HoldingBuilder<N = f64>
+where
+    N: Number,
+{
+    selector: Option<Arc<dyn LotSelector<N>>>,
+    removal: Option<RemovalTreatment>,
+    date_order: Option<DateOrder>,
+    price_decimals: Option<u32>,
+    basis_split: Option<BasisSplit>,
+    dust_threshold: Option<f64>,
+    dust: Option<DustTreatment>,
+    conflict: Option<String>,
+}
+
+impl<N> Default for HoldingBuilder<N>
+where
+    N: Number,
+{
+    fn default() -> Self {
+        HoldingBuilder {
+            selector: None,
+            removal: None,
+            date_order: None,
+            price_decimals: None,
+            basis_split: None,
+            dust_threshold: None,
+            dust: None,
+            conflict: None,
+        }
     }
 }
 
-impl fmt::Display for Holding {
+impl<N> HoldingBuilder<N>
+where
+    N: Number,
+{
+    /// Lot method used to relieve inventory, default is `Fifo`
+    pub fn selector<S>(mut self, selector: S) -> Self
+    where
+        S: LotSelector<N> + 'static,
+    {
+        if self.selector.is_some() {
+            self.conflict(format!("lot selector set twice, {:?}", selector));
+        }
+        self.selector = Some(Arc::new(selector));
+        self
+    }
+
+    /// Treatment of `Remove` inventory changes, default is `RemovalTreatment::Unreported`
+    pub fn removal(mut self, removal: RemovalTreatment) -> Self {
+        set_once(&mut self.removal, removal, &mut self.conflict, "removal");
+        self
+    }
+
+    /// Order a batch is applied in, default is `DateOrder::Strict`
+    pub fn date_order(mut self, date_order: DateOrder) -> Self {
+        set_once(
+            &mut self.date_order,
+            date_order,
+            &mut self.conflict,
+            "date order",
+        );
+        self
+    }
+
+    /// Decimals the price of `Holding::position` is rounded to, at most `MAX_DECIMALS`
+    pub fn price_decimals(mut self, decimals: u32) -> Self {
+        set_once(
+            &mut self.price_decimals,
+            decimals,
+            &mut self.conflict,
+            "price decimals",
+        );
+        self
+    }
+
+    /// How the basis of a partly closed lot is divided, default is `BasisSplit::Remainder`
+    pub fn basis_split(mut self, basis_split: BasisSplit) -> Self {
+        set_once(
+            &mut self.basis_split,
+            basis_split,
+            &mut self.conflict,
+            "basis split",
+        );
+        self
+    }
+
+    /// Quantity held below which inventory left after a close is dust, and what is done with it.  A
+    /// threshold other than 0 can't be less than the margin of error of a quantity, 0.0000000001.
+    pub fn dust(mut self, threshold: f64, treatment: DustTreatment) -> Self {
+        set_once(
+            &mut self.dust_threshold,
+            threshold,
+            &mut self.conflict,
+            "dust threshold",
+        );
+        set_once(
+            &mut self.dust,
+            treatment,
+            &mut self.conflict,
+            "dust treatment",
+        );
+        self
+    }
+
+    /// Empty holding with the configuration, or the first conflicting or invalid option
+    pub fn build(self) -> Result<Holding<N>, Error> {
+        if let Some(conflict) = self.conflict {
+            return Err(Error::Config(conflict));
+        }
+        let default = HoldingConfig::default();
+        let config = HoldingConfig {
+            removal: self.removal.unwrap_or(default.removal),
+            date_order: self.date_order.unwrap_or(default.date_order),
+            price_decimals: self.price_decimals.unwrap_or(default.price_decimals),
+            basis_split: self.basis_split.unwrap_or(default.basis_split),
+            dust_threshold: self.dust_threshold.unwrap_or(default.dust_threshold),
+            dust: self.dust.unwrap_or(default.dust),
+        };
+        if config.price_decimals > MAX_DECIMALS {
+            return Err(Error::Config(format!(
+                "price decimals {} is more than {}",
+                config.price_decimals, MAX_DECIMALS
+            )));
+        }
+        if config.dust_threshold.is_nan() || config.dust_threshold < 0.0 {
+            return Err(Error::Config(format!(
+                "dust threshold {} is not zero or positive",
+                config.dust_threshold
+            )));
+        }
+        // residual inventory below the margin is only reset when there is no dust threshold
+        if config.dust_threshold > 0.0 && N::from_f64(config.dust_threshold) < N::margin() {
+            return Err(Error::Config(format!(
+                "dust threshold {} is less than the quantity margin {}",
+                config.dust_threshold,
+                N::margin()
+            )));
+        }
+        if let BasisSplit::Rounded(decimals) = config.basis_split {
+            if decimals > MAX_DECIMALS {
+                return Err(Error::Config(format!(
+                    "basis split decimals {} is more than {}",
+                    decimals, MAX_DECIMALS
+                )));
+            }
+        }
+        Ok(Holding {
+            unrealized: Vec::new(),
+            direction: None,
+            config,
+            selector: self.selector.unwrap_or_else(|| Arc::new(Fifo)),
+            last_date: None,
+            dust: Vec::new(),
+            dust_basis: N::zero(),
+        })
+    }
+
+    // keep the first conflict found
+    fn conflict(&mut self, conflict: String) {
+        self.conflict.get_or_insert(conflict);
+    }
+}
+
+// set an option, a different value than one already set is a conflict
+fn set_once<V>(option: &mut Option<V>, value: V, conflict: &mut Option<String>, name: &str)
+where
+    V: PartialEq + fmt::Debug,
+{
+    match option {
+        Some(set) if *set != value => {
+            conflict.get_or_insert(format!("{} set to {:?} and {:?}", name, set, value));
+        }
+        _ => *option = Some(value),
+    }
+}
+
+// stable sort by date - same day acquisitions are applied before disposals
+pub(crate) fn sort_batch<T, N>(batch: &mut [T])
+where
+    T: Inventory<N>,
+    N: Number,
+{
+    batch.sort_by_key(|inv| {
+        let disposal = matches!(inv.itype(), InventoryType::Short | InventoryType::Remove);
+        (inv.date(), disposal)
+    });
+}
+
+impl<N> fmt::Display for Holding<N>
+where
+    N: Number,
+{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
@@ -226,6 +875,41 @@ impl fmt::Display for Holding {
 mod tests {
 
     use super::*;
+    use crate::lot::{AverageCost, Lifo};
+
+    #[test]
+    fn builder_rejects_conflicting_options() {
+        let holding: Holding = Holding::builder()
+            .removal(RemovalTreatment::AtZero)
+            .removal(RemovalTreatment::AtZero)
+            .price_decimals(2)
+            .build()
+            .unwrap();
+        assert_eq!(holding.config().removal(), RemovalTreatment::AtZero);
+        assert_eq!(holding.config().date_order(), DateOrder::Strict);
+
+        let conflicts: [Result<Holding, Error>; 3] = [
+            Holding::builder()
+                .removal(RemovalTreatment::AtCost)
+                .removal(RemovalTreatment::AtMarket)
+                .build(),
+            Holding::builder()
+                .selector(Lifo)
+                .selector(AverageCost)
+                .build(),
+            Holding::builder().price_decimals(20).build(),
+        ];
+        for conflict in conflicts {
+            assert!(matches!(conflict, Err(Error::Config(_))));
+        }
+    }
+
+    #[test]
+    fn position_price_rounded_to_configured_decimals() {
+        let mut holding = Holding::builder().price_decimals(2).build().unwrap();
+        holding.add_inventory(URealized::from("2020-01-01,3.0,-100.0"));
+        assert_eq!(holding.position(), (3.0, 33.33, -100.0));
+    }
 
     #[test]
     fn create_new_gains_from_unrealized_slice() {
@@ -288,14 +972,14 @@ mod tests {
 
         // tests equal inv_change
         let mut holding = Holding::from(&starting_ur[..]);
-        let matches = holding.split_matching_first(URealized::from("2020-04-01,-100.0,3000.0"));
+        let matches = holding.split_matching_lot(0, URealized::from("2020-04-01,-100.0,3000.0"));
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0], URealized::from("2020-04-01,-100.0,3000.0"));
         assert_eq!(holding.unrealized, starting_ur);
 
         // tests smaller inv_change
         let mut holding = Holding::from(&starting_ur[..]);
-        let matches = holding.split_matching_first(URealized::from("2020-04-01,-50.0,1500.0"));
+        let matches = holding.split_matching_lot(0, URealized::from("2020-04-01,-50.0,1500.0"));
         assert_eq!(matches.len(), 1);
         assert_eq!(matches[0], URealized::from("2020-04-01,-50.0,1500.0"));
         assert_eq!(
@@ -310,7 +994,7 @@ mod tests {
 
         // tests larger inv_change
         let mut holding = Holding::from(&starting_ur[..]);
-        let matches = holding.split_matching_first(URealized::from("2020-04-01,-350.0,10500.0"));
+        let matches = holding.split_matching_lot(0, URealized::from("2020-04-01,-350.0,10500.0"));
         assert_eq!(matches.len(), 2);
         assert_eq!(matches[0], URealized::from("2020-04-01,-100.0,3000.0"));
         assert_eq!(matches[1], URealized::from("2020-04-01,-250.0,7500.0"));
@@ -1,19 +1,46 @@
+use crate::fx::Currency;
+use crate::lot::LotRef;
+use crate::num::Number;
 use chrono::NaiveDate;
 
 /// Trait that identifies an inventory change.  
 ///
-/// Implementation of this trait is needed when adding transactions to a holding.
-pub trait Inventory {
-    fn basis(&self) -> f64;
+/// Implementation of this trait is needed when adding transactions to a holding.  Quantity and basis
+/// are `f64` unless another `Number` is given.
+pub trait Inventory<N = f64>
+where
+    N: Number,
+{
+    fn basis(&self) -> N;
 
-    fn quantity(&self) -> f64;
+    fn quantity(&self) -> N;
 
     fn date(&self) -> NaiveDate;
 
     fn itype(&self) -> InventoryType;
 
+    /// Fee paid for the inventory change as a positive amount.  The fee of an inventory change that
+    /// opens inventory is added to the cost of the `URealized`, the fee of a close reduces the proceeds
+    /// of the `Realized`.  `basis` is before the fee.
+    ///
+    /// The fee has to be pro-rated by `VolumeSplit::split`.
+    fn fee(&self) -> N {
+        N::zero()
+    }
+
+    /// Specific lot referenced by the inventory change.  When opening inventory the lot id tags the new
+    /// lot, when closing inventory only the referenced lot is relieved.
+    fn lot(&self) -> Option<LotRef> {
+        None
+    }
+
+    /// Currency of the basis and fee, `None` for the reporting currency
+    fn currency(&self) -> Option<Currency> {
+        None
+    }
+
     fn direction_type(&self) -> InventoryType {
-        if self.quantity() > 0.0 {
+        if self.quantity() > N::zero() {
             InventoryType::Long
         } else {
             InventoryType::Short
@@ -22,8 +49,8 @@ pub trait Inventory {
 }
 
 /// VolumeSplit is to divide into two parts.  Quantity is always positive.
-pub trait VolumeSplit<T> {
-    fn split(&self, quantity: f64) -> (T, T);
+pub trait VolumeSplit<T, N = f64> {
+    fn split(&self, quantity: N) -> (T, T);
 }
 
 /// Inventory Types to identify the type of inventory change.
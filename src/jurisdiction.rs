@@ -0,0 +1,135 @@
+use crate::gain::{GainType, HoldingPeriod};
+use crate::num::Number;
+use crate::realized::Realized;
+use std::fmt;
+
+/// Tax rules of a jurisdiction that classify a `Realized` and work out how much of the gain/loss is
+/// taxable.
+///
+/// Implement this trait to add your own jurisdiction.  Only `taxable` is required, the classification
+/// defaults to the one year `HoldingPeriod`.
+pub trait Jurisdiction<N = f64>: fmt::Debug + Send + Sync
+where
+    N: Number,
+{
+    /// Threshold a holding has to be held past to be long-term
+    fn holding_period(&self) -> HoldingPeriod {
+        HoldingPeriod::default()
+    }
+
+    /// Short-term or long-term based on the open and close date of `r`
+    fn gain_type(&self, r: &Realized<N>) -> GainType {
+        r.gain_type(self.holding_period())
+    }
+
+    /// Portion of the realized gain/loss of `r` that is taxable, a loss is negative
+    fn taxable(&self, r: &Realized<N>) -> N;
+}
+
+/// United States - all of the gain/loss is taxable, long-term after one year and a day.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UnitedStates;
+
+impl<N> Jurisdiction<N> for UnitedStates
+where
+    N: Number,
+{
+    fn taxable(&self, r: &Realized<N>) -> N {
+        r.realized()
+    }
+}
+
+/// Germany private sales - gains and losses are tax-free when held for more than one year, for example
+/// crypto.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Germany;
+
+impl<N> Jurisdiction<N> for Germany
+where
+    N: Number,
+{
+    fn taxable(&self, r: &Realized<N>) -> N {
+        match self.gain_type(r) {
+            GainType::Shortterm => r.realized(),
+            GainType::Longterm => N::zero(),
+        }
+    }
+}
+
+/// Australia - 50% CGT discount on gains held for at least 12 months, not counting the day acquired.
+///
+/// The discount is applied to each gain separately, losses are taxable in full.  Netting of losses
+/// before the discount is left to the report.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Australia;
+
+/// Share of a long-term gain that is discounted in Australia
+pub const AUSTRALIA_CGT_DISCOUNT: f64 = 0.5;
+
+impl<N> Jurisdiction<N> for Australia
+where
+    N: Number,
+{
+    fn taxable(&self, r: &Realized<N>) -> N {
+        match self.gain_type(r) {
+            GainType::Longterm if r.realized() > N::zero() => {
+                r.realized() * N::from_f64(1.0 - AUSTRALIA_CGT_DISCOUNT)
+            }
+            _ => r.realized(),
+        }
+    }
+}
+
+/// Total taxable gain/loss of the slice of `Realized` under `jurisdiction`
+pub fn total_taxable<J, N>(r: &[Realized<N>], jurisdiction: &J) -> N
+where
+    J: Jurisdiction<N> + ?Sized,
+    N: Number,
+{
+    r.iter().map(|r| jurisdiction.taxable(r)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn set_realized() -> [Realized; 3] {
+        [
+            Realized::from("2021-01-01,-100.0,3500.0,2020-01-01,-2500.0"),
+            Realized::from("2021-01-02,-100.0,3500.0,2020-01-01,-2500.0"),
+            Realized::from("2021-01-02,-100.0,2000.0,2020-01-01,-2500.0"),
+        ]
+    }
+
+    #[test]
+    fn united_states_taxes_all_gains() {
+        let realized = set_realized();
+        assert_eq!(UnitedStates.gain_type(&realized[0]), GainType::Shortterm);
+        assert_eq!(UnitedStates.gain_type(&realized[1]), GainType::Longterm);
+        assert_eq!(total_taxable(&realized, &UnitedStates), 1500.0);
+    }
+
+    #[test]
+    fn germany_long_term_is_tax_free() {
+        let realized = set_realized();
+        assert_eq!(Germany.taxable(&realized[0]), 1000.0);
+        assert_eq!(Germany.taxable(&realized[1]), 0.0);
+        assert_eq!(Germany.taxable(&realized[2]), 0.0);
+    }
+
+    #[test]
+    fn australia_discounts_long_term_gains_only() {
+        let realized = set_realized();
+        assert_eq!(Australia.taxable(&realized[0]), 1000.0);
+        assert_eq!(Australia.taxable(&realized[1]), 500.0);
+        assert_eq!(Australia.taxable(&realized[2]), -500.0);
+
+        let jurisdictions: [&dyn Jurisdiction; 3] = [&UnitedStates, &Germany, &Australia];
+        let totals: Vec<f64> = jurisdictions
+            .iter()
+            .map(|j| total_taxable(&realized, *j))
+            .collect();
+        assert_eq!(totals, [1500.0, 1000.0, 1000.0]);
+    }
+}
@@ -0,0 +1,398 @@
+use crate::error::Error;
+use crate::holding::Holding;
+use crate::inventory::{Inventory, VolumeSplit};
+use crate::num::Number;
+use crate::realized::Realized;
+use crate::unrealized::URealized;
+use chrono::NaiveDate;
+
+/// Ledger keeps the full history of inventory changes applied to a `Holding`.
+///
+/// Inventory changes can be inserted, amended or deleted at any date.  The holding inventory and all
+/// `Realized` are recomputed from the first changed date forward and a `LedgerDiff` of the realized
+/// gains that changed is returned.  The state of the holding is checkpointed every
+/// `CHECKPOINT_INTERVAL` inventory changes so only history since the checkpoint before a change needs
+/// to be replayed, without keeping a copy of the holding for every inventory change.
+#[derive(Debug, Clone)]
+pub struct Ledger<T, N = f64>
+where
+    N: Number,
+{
+    start: Holding<N>,
+    history: Vec<T>,
+    // holding after each multiple of CHECKPOINT_INTERVAL inventory changes
+    checkpoints: Vec<Holding<N>>,
+    realized: Vec<Vec<Realized<N>>>,
+    current: Holding<N>,
+}
+
+/// Number of inventory changes between the holding states kept by a `Ledger`
+pub const CHECKPOINT_INTERVAL: usize = 64;
+
+/// Realized gains removed and added by a change to a `Ledger`
+#[derive(Debug, PartialEq, Clone)]
+pub struct LedgerDiff<N = f64> {
+    pub removed: Vec<Realized<N>>,
+    pub added: Vec<Realized<N>>,
+}
+
+impl<N> Default for LedgerDiff<N> {
+    fn default() -> Self {
+        LedgerDiff {
+            removed: Vec::new(),
+            added: Vec::new(),
+        }
+    }
+}
+
+impl<N> LedgerDiff<N> {
+    /// True if no realized gains changed
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty() && self.added.is_empty()
+    }
+}
+
+/// State of a holding as of a date - inventory, position `(quantity, price, basis)` and all
+/// realized gains up to and including the date
+#[derive(Debug, PartialEq, Clone)]
+pub struct HoldingSnapshot<N = f64> {
+    pub date: NaiveDate,
+    pub inventory: Vec<URealized<N>>,
+    pub position: (N, N, N),
+    pub realized: Vec<Realized<N>>,
+}
+
+impl<T, N> Ledger<T, N>
+where
+    T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    N: Number,
+{
+    /// Empty ledger starting from `holding`, which sets the configuration and any starting inventory
+    pub fn new(holding: Holding<N>) -> Self {
+        Ledger {
+            current: holding.clone(),
+            start: holding,
+            history: Vec::new(),
+            checkpoints: Vec::new(),
+            realized: Vec::new(),
+        }
+    }
+
+    /// Ledger starting from `holding` with a history of inventory changes in any order
+    pub fn from_transactions(holding: Holding<N>, invs: &[T]) -> Result<Self, Error> {
+        let mut ledger = Ledger::new(holding);
+        for inv in invs {
+            ledger.history.insert(ledger.position(inv), *inv);
+        }
+        ledger.replay(0)?;
+        Ok(ledger)
+    }
+
+    /// Insert an inventory change at its date.  Changes on the same date keep the order inserted.
+    pub fn insert(&mut self, inv: T) -> Result<LedgerDiff<N>, Error> {
+        let i = self.position(&inv);
+        let mut history = self.history.clone();
+        history.insert(i, inv);
+        self.update(history, i)
+    }
+
+    /// Replace the inventory change at `index` of `history`, moving it if the date changed
+    pub fn amend(&mut self, index: usize, inv: T) -> Result<LedgerDiff<N>, Error> {
+        self.check_index(index)?;
+        let mut history = self.history.clone();
+        history.remove(index);
+        let i = position(&history, &inv);
+        history.insert(i, inv);
+        self.update(history, index.min(i))
+    }
+
+    /// Delete the inventory change at `index` of `history`
+    pub fn delete(&mut self, index: usize) -> Result<LedgerDiff<N>, Error> {
+        self.check_index(index)?;
+        let mut history = self.history.clone();
+        history.remove(index);
+        self.update(history, index)
+    }
+
+    /// Inventory changes sorted by date
+    pub fn history(&self) -> &[T] {
+        &self.history
+    }
+
+    /// Holding after all inventory changes
+    pub fn holding(&self) -> &Holding<N> {
+        &self.current
+    }
+
+    /// Current inventory after all inventory changes
+    pub fn inventory(&self) -> Vec<URealized<N>> {
+        self.holding().inventory()
+    }
+
+    /// All realized gains in order of the inventory changes
+    pub fn realized(&self) -> Vec<Realized<N>> {
+        self.realized.iter().flatten().cloned().collect()
+    }
+
+    /// Holding as of the end of `date` including all inventory changes on or before the date
+    pub fn as_of(&self, date: NaiveDate) -> HoldingSnapshot<N> {
+        let n = self.history.partition_point(|h| h.date() <= date);
+        let holding = self.state(n);
+        HoldingSnapshot {
+            date,
+            inventory: holding.inventory(),
+            position: holding.position(),
+            realized: self.realized[..n].iter().flatten().cloned().collect(),
+        }
+    }
+
+    // index to insert inventory change after all changes on or before its date
+    fn position(&self, inv: &T) -> usize {
+        position(&self.history, inv)
+    }
+
+    fn check_index(&self, index: usize) -> Result<(), Error> {
+        if index < self.history.len() {
+            Ok(())
+        } else {
+            Err(Error::HistoryIndex(index, self.history.len()))
+        }
+    }
+
+    // holding after the first `n` inventory changes, replayed from the checkpoint before
+    fn state(&self, n: usize) -> Holding<N> {
+        let k = n / CHECKPOINT_INTERVAL;
+        let mut holding = match k {
+            0 => self.start.clone(),
+            _ => self.checkpoints[k - 1].clone(),
+        };
+        for inv in self.history[k * CHECKPOINT_INTERVAL..n].iter() {
+            // history up to n has already been applied without an error
+            let _ = holding.try_add_transaction(inv);
+        }
+        holding
+    }
+
+    // recompute from index `from` and only keep the new history if it can be applied
+    fn update(&mut self, history: Vec<T>, from: usize) -> Result<LedgerDiff<N>, Error> {
+        let previous = (
+            std::mem::replace(&mut self.history, history),
+            self.checkpoints.clone(),
+            self.realized.clone(),
+            self.current.clone(),
+        );
+        let old: Vec<Realized<N>> = self.realized[from.min(self.realized.len())..]
+            .iter()
+            .flatten()
+            .cloned()
+            .collect();
+        if let Err(e) = self.replay(from) {
+            self.history = previous.0;
+            self.checkpoints = previous.1;
+            self.realized = previous.2;
+            self.current = previous.3;
+            return Err(e);
+        }
+        let new: Vec<Realized<N>> = self.realized[from..].iter().flatten().cloned().collect();
+        Ok(diff(old, new))
+    }
+
+    // apply history from index `from` to the state before it
+    fn replay(&mut self, from: usize) -> Result<(), Error> {
+        self.checkpoints.truncate(from / CHECKPOINT_INTERVAL);
+        self.realized.truncate(from);
+        let mut holding = self.state(from);
+        for (i, inv) in self.history.iter().enumerate().skip(from) {
+            self.realized.push(holding.try_add_transaction(inv)?);
+            if (i + 1) % CHECKPOINT_INTERVAL == 0 {
+                self.checkpoints.push(holding.clone());
+            }
+        }
+        self.current = holding;
+        Ok(())
+    }
+}
+
+fn position<T, N>(history: &[T], inv: &T) -> usize
+where
+    T: Inventory<N>,
+    N: Number,
+{
+    history.partition_point(|h| h.date() <= inv.date())
+}
+
+// realized in old and not new are removed, in new and not old are added
+fn diff<N>(mut old: Vec<Realized<N>>, new: Vec<Realized<N>>) -> LedgerDiff<N>
+where
+    N: Number,
+{
+    let mut added = Vec::new();
+    for r in new {
+        match old.iter().position(|o| *o == r) {
+            Some(i) => {
+                old.remove(i);
+            }
+            None => added.push(r),
+        }
+    }
+    LedgerDiff {
+        removed: old,
+        added,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::inventory::InventoryType;
+    use crate::transaction::Transaction;
+
+    fn set_ledger() -> Ledger<Transaction> {
+        let transactions = [
+            Transaction::from("2020-01-01,long,100.0,20.0"),
+            Transaction::from("2020-03-01,long,100.0,30.0"),
+            Transaction::from("2020-04-01,short,150.0,35.0"),
+        ];
+        Ledger::from_transactions(Holding::default(), &transactions).unwrap()
+    }
+
+    #[test]
+    fn insert_backdated_transaction_replays_realized() {
+        let mut ledger = set_ledger();
+        let diff = ledger
+            .insert(Transaction::from("2020-02-01,short,50.0,25.0"))
+            .unwrap();
+
+        assert_eq!(
+            diff.removed,
+            [
+                Realized::from("2020-04-01,-100.0,3500.0,2020-01-01,-2000.0"),
+                Realized::from("2020-04-01,-50.0,1750.0,2020-03-01,-1500.0"),
+            ]
+        );
+        assert_eq!(
+            diff.added,
+            [
+                Realized::from("2020-02-01,-50.0,1250.0,2020-01-01,-1000.0"),
+                Realized::from("2020-04-01,-50.0,1750.0,2020-01-01,-1000.0"),
+                Realized::from("2020-04-01,-100.0,3500.0,2020-03-01,-3000.0"),
+            ]
+        );
+        assert!(ledger.inventory().is_empty());
+        assert_eq!(
+            ledger.history()[1],
+            Transaction::from("2020-02-01,short,50.0,25.0")
+        );
+    }
+
+    #[test]
+    fn amend_and_delete_only_report_changed_realized() {
+        let mut ledger = set_ledger();
+        let diff = ledger
+            .amend(1, Transaction::from("2020-03-01,long,100.0,32.0"))
+            .unwrap();
+        assert_eq!(
+            diff,
+            LedgerDiff {
+                removed: vec![Realized::from("2020-04-01,-50.0,1750.0,2020-03-01,-1500.0")],
+                added: vec![Realized::from("2020-04-01,-50.0,1750.0,2020-03-01,-1600.0")],
+            }
+        );
+
+        let diff = ledger.delete(2).unwrap();
+        assert!(diff.added.is_empty());
+        assert_eq!(diff.removed.len(), 2);
+        assert_eq!(
+            ledger.inventory(),
+            [
+                URealized::from("2020-01-01,100.0,-2000.0"),
+                URealized::from("2020-03-01,100.0,-3200.0"),
+            ]
+        );
+        assert!(ledger.realized().is_empty());
+    }
+
+    #[test]
+    fn snapshot_as_of_date() {
+        let ledger = set_ledger();
+
+        let snapshot = ledger.as_of(NaiveDate::from_ymd_opt(2019, 12, 31).unwrap());
+        assert!(snapshot.inventory.is_empty());
+        assert_eq!(snapshot.position, (0.0, 0.0, 0.0));
+
+        let snapshot = ledger.as_of(NaiveDate::from_ymd_opt(2020, 3, 31).unwrap());
+        assert_eq!(
+            snapshot.inventory,
+            [
+                URealized::from("2020-01-01,100.0,-2000.0"),
+                URealized::from("2020-03-01,100.0,-3000.0"),
+            ]
+        );
+        assert_eq!(snapshot.position, (200.0, 25.0, -5000.0));
+        assert!(snapshot.realized.is_empty());
+
+        // changes on the date are included
+        let snapshot = ledger.as_of(NaiveDate::from_ymd_opt(2020, 4, 1).unwrap());
+        assert_eq!(snapshot.position, (50.0, 30.0, -1500.0));
+        assert_eq!(snapshot.realized, ledger.realized());
+    }
+
+    #[test]
+    fn index_outside_of_history_is_an_error() {
+        let mut ledger = set_ledger();
+        assert_eq!(ledger.delete(3), Err(Error::HistoryIndex(3, 3)));
+        assert_eq!(
+            ledger.amend(5, Transaction::from("2020-03-01,long,100.0,32.0")),
+            Err(Error::HistoryIndex(5, 3))
+        );
+        assert_eq!(ledger.history().len(), 3);
+    }
+
+    #[test]
+    fn long_history_replays_from_checkpoints() {
+        let transactions: Vec<Transaction> = (0..3 * CHECKPOINT_INTERVAL)
+            .map(|i| {
+                let date =
+                    NaiveDate::from_ymd_opt(2020, 1, 1).unwrap() + chrono::Duration::days(i as i64);
+                match i % 3 {
+                    2 => Transaction::new(date, InventoryType::Short, 15.0, 30.0 + i as f64),
+                    _ => Transaction::new(date, InventoryType::Long, 10.0, 20.0 + i as f64),
+                }
+            })
+            .collect();
+        let mut ledger = Ledger::from_transactions(Holding::default(), &transactions).unwrap();
+        assert_eq!(ledger.checkpoints.len(), 3);
+
+        // amend before the first checkpoint and compare with a ledger built from the amended history
+        let amended = Transaction::new(transactions[10].date(), InventoryType::Long, 10.0, 99.0);
+        ledger.amend(10, amended).unwrap();
+        let mut history = transactions.clone();
+        history[10] = amended;
+        let rebuilt = Ledger::from_transactions(Holding::default(), &history).unwrap();
+
+        assert_eq!(ledger.realized(), rebuilt.realized());
+        assert_eq!(ledger.inventory(), rebuilt.inventory());
+        let date = transactions[100].date();
+        assert_eq!(ledger.as_of(date), rebuilt.as_of(date));
+        assert_eq!(
+            ledger.as_of(date).realized.len(),
+            rebuilt.realized[..101].concat().len()
+        );
+    }
+
+    #[test]
+    fn failed_change_leaves_ledger_unchanged() {
+        let mut ledger = set_ledger();
+        let realized = ledger.realized();
+        assert!(ledger
+            .insert(Transaction::from("2020-02-01,short,50.0,25.0,2020-03-01"))
+            .is_err());
+        assert_eq!(ledger.history().len(), 3);
+        assert_eq!(ledger.realized(), realized);
+        assert_eq!(
+            ledger.inventory(),
+            [URealized::from("2020-03-01,50.0,-1500.0")]
+        );
+    }
+}
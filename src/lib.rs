@@ -2,13 +2,15 @@
 //! are added, the inventory is updated or removed into a realized gain.
 //!
 //! - `Holding` - maintains the current inventory and determines change impacts in inventory/unrealized
-//!     and realized gains/loss
+//!   and realized gains/loss
 //!
 //! - `URealized` - is a transaction record not realized (open position)
 //! - `Realized` - is a combination of a transaction open and matching close, captures gain/loss
 //! - `Transaction` - is a record of inventory change.  Can be replaced by a user defined struct that implements `Inventory` and `VolumeSplit` trait
 //!
-//! Assumes FIFO for maintaining holding inventory.  Future plans to add Lot, Avg Weight, and LIFO.
+//! Assumes FIFO for maintaining holding inventory unless the holding is created with another `LotSelector`
+//! such as `Lifo`, `Hifo`, `Lofo` or `AverageCost`.  An inventory change can also close a specific lot
+//! by carrying a `LotRef`.
 //!
 //! Example
 //! ```
@@ -42,10 +44,35 @@
 
 const MARGIN_ERROR_QUANTITY: f64 = 0.0000000001;
 
+/// `Accounts` holding assets across accounts or wallets with transfers that keep the basis of lots
+pub mod account;
+/// Canadian superficial loss rule for average cost holdings
+pub mod ca;
+/// typed `Holding` configuration set with `Holding::builder`
+pub mod config;
+/// crate `Error` returned by fallible functions instead of panicking
+pub mod error;
+/// fees paid in another asset, such as ETH gas, disposed of at market value
+pub mod fee;
+/// `FxRates` table converting realized gains to a reporting currency
+pub mod fx;
+/// `GainType` short-term or long-term and the `HoldingPeriod` used to classify it
+pub mod gain;
 /// holds struct and functions dealing with a `Holding`
 pub mod holding;
 /// traits to use with holding if user defined struct instead of using `Transaction`
 pub mod inventory;
+/// `Jurisdiction` tax rules that classify a `Realized` and compute the taxable portion of the gain
+pub mod jurisdiction;
+/// `Ledger` keeps the history of a holding to insert, amend or delete inventory changes at any date
+/// and to query the holding as of a date
+pub mod ledger;
+/// `LotSelector` trait and built in strategies for picking which inventory a holding relieves
+pub mod lot;
+/// `Number` trait for the numeric type of quantities and basis, `f64` or an exact decimal
+pub mod num;
+/// `Portfolio` routing inventory changes to the `Holding` of each asset
+pub mod portfolio;
 /// struct and functions related to `Realized` - realized gains/losses
 pub mod realized;
 /// defined `Transaction` struct to use in identifying inventory changes
@@ -53,5 +80,10 @@ pub mod transaction;
 /// struct and functions related to `URealized` - unrealized inventory
 pub mod unrealized;
 
+/// UK share matching - same day, bed and breakfast and Section 104 pool
+pub mod uk;
+/// US wash sale rule disallowing losses and adjusting replacement lots
+pub mod wash;
+
 #[cfg(test)]
 mod tests {}
@@ -0,0 +1,250 @@
+use crate::error::{parse_date, parse_field, Error};
+use crate::inventory::Inventory;
+use crate::num::Number;
+use crate::unrealized::URealized;
+use chrono::NaiveDate;
+use std::fmt;
+use std::str::FromStr;
+
+/// Reference to a specific lot of inventory by open date and an optional lot id.
+///
+/// An inventory change that opens inventory tags the new `URealized` with the lot id.  A change that
+/// closes inventory relieves exactly the referenced lot.  Without a lot id the first lot opened on the
+/// date is referenced.
+// open date, lot id
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LotRef(NaiveDate, Option<u32>);
+
+impl LotRef {
+    pub fn new(date: NaiveDate, id: Option<u32>) -> Self {
+        LotRef(date, id)
+    }
+
+    pub fn date(&self) -> NaiveDate {
+        self.0
+    }
+
+    pub fn id(&self) -> Option<u32> {
+        self.1
+    }
+
+    /// True if the lot `ur` is the one referenced
+    pub fn matches<N>(&self, ur: &URealized<N>) -> bool
+    where
+        N: Number,
+    {
+        self.0 == ur.date() && (self.1.is_none() || self.1 == ur.lot_id())
+    }
+}
+
+impl FromStr for LotRef {
+    type Err = Error;
+    /// in the form of `"yyyy-mm-dd"` or `"yyyy-mm-dd#lot_id"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let field: Vec<&str> = s.split('#').collect();
+        Ok(LotRef(
+            parse_date(&field, 0, "lot open date")?,
+            match field.len() {
+                1 => None,
+                _ => Some(parse_field(&field, 1, "lot id")?),
+            },
+        ))
+    }
+}
+
+impl From<&str> for LotRef {
+    /// Same form as `FromStr` but panics if the text can't be parsed
+    fn from(s: &str) -> Self {
+        s.parse().unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl fmt::Display for LotRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.1 {
+            Some(id) => write!(f, "{}#{}", self.0, id),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// Strategy a `Holding` consults to decide which `URealized` lot is relieved next by an inventory
+/// change in the opposite direction.
+///
+/// Implement this trait to plug in your own selection, for example oldest long-term lot first.  The
+/// built in strategies work with any `Number`.
+pub trait LotSelector<N = f64>: fmt::Debug + Send + Sync
+where
+    N: Number,
+{
+    /// Index into `lots` of the inventory to relieve next.
+    ///
+    /// `lots` is never empty and is kept in the order inventory was added.  `close` is the
+    /// remaining inventory change that still needs to be matched.
+    fn select(&self, lots: &[URealized<N>], close: &URealized<N>) -> usize;
+
+    /// When true the holding pools all added inventory into a single lot at an average cost
+    fn pooled(&self) -> bool {
+        false
+    }
+}
+
+/// First in first out - oldest inventory is relieved first.  Default for a `Holding`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Fifo;
+
+impl<N> LotSelector<N> for Fifo
+where
+    N: Number,
+{
+    fn select(&self, _lots: &[URealized<N>], _close: &URealized<N>) -> usize {
+        0
+    }
+}
+
+/// Last in first out - most recently added inventory is relieved first.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Lifo;
+
+impl<N> LotSelector<N> for Lifo
+where
+    N: Number,
+{
+    fn select(&self, lots: &[URealized<N>], _close: &URealized<N>) -> usize {
+        lots.len() - 1
+    }
+}
+
+/// Highest in first out - lot with the highest per unit cost is relieved first.
+///
+/// Per unit cost is the price `-basis()/quantity()` of each lot, for short inventory this is the price
+/// received.  Ties are relieved oldest first.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Hifo;
+
+impl<N> LotSelector<N> for Hifo
+where
+    N: Number,
+{
+    fn select(&self, lots: &[URealized<N>], _close: &URealized<N>) -> usize {
+        let mut highest = 0;
+        for (i, lot) in lots.iter().enumerate() {
+            if unit_cost(lot) > unit_cost(&lots[highest]) {
+                highest = i;
+            }
+        }
+        highest
+    }
+}
+
+/// Lowest in first out - lot with the lowest per unit cost is relieved first.
+///
+/// Per unit cost is calculated the same as `Hifo`.  Ties are relieved oldest first.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Lofo;
+
+impl<N> LotSelector<N> for Lofo
+where
+    N: Number,
+{
+    fn select(&self, lots: &[URealized<N>], _close: &URealized<N>) -> usize {
+        let mut lowest = 0;
+        for (i, lot) in lots.iter().enumerate() {
+            if unit_cost(lot) < unit_cost(&lots[lowest]) {
+                lowest = i;
+            }
+        }
+        lowest
+    }
+}
+
+// price per unit of a lot
+fn unit_cost<N>(lot: &URealized<N>) -> N
+where
+    N: Number,
+{
+    -lot.basis() / lot.quantity()
+}
+
+/// Average cost - all inventory is pooled into a single lot with a running adjusted cost base.
+///
+/// Each close is relieved pro-rata against the pooled basis, the average price from `Holding::position`.
+/// The pooled lot keeps the open date of the first inventory added to the pool.  Works with mutual funds
+/// and Canadian adjusted cost base reporting.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AverageCost;
+
+impl<N> LotSelector<N> for AverageCost
+where
+    N: Number,
+{
+    fn select(&self, _lots: &[URealized<N>], _close: &URealized<N>) -> usize {
+        0
+    }
+
+    fn pooled(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn set_lots() -> [URealized; 3] {
+        [
+            URealized::from("2020-01-01,100.0,-2000.0"),
+            URealized::from("2020-02-01,100.0,-2500.0"),
+            URealized::from("2020-03-01,100.0,-3000.0"),
+        ]
+    }
+
+    #[test]
+    fn lot_ref_matches_date_and_lot_id() {
+        let ur = URealized::from("2020-01-01,100.0,-2000.0,3");
+        assert!(LotRef::from("2020-01-01").matches(&ur));
+        assert!(LotRef::from("2020-01-01#3").matches(&ur));
+        assert!(!LotRef::from("2020-01-01#2").matches(&ur));
+        assert!(!LotRef::from("2020-02-01").matches(&ur));
+        assert_eq!(LotRef::from("2020-01-01#3").to_string(), "2020-01-01#3");
+    }
+
+    #[test]
+    fn fifo_selects_oldest_lot() {
+        let close = URealized::from("2020-04-01,-50.0,1500.0");
+        assert_eq!(Fifo.select(&set_lots(), &close), 0);
+    }
+
+    #[test]
+    fn lifo_selects_newest_lot() {
+        let close = URealized::from("2020-04-01,-50.0,1500.0");
+        assert_eq!(Lifo.select(&set_lots(), &close), 2);
+    }
+
+    #[test]
+    fn hifo_and_lofo_select_by_unit_cost() {
+        let lots = [
+            URealized::from("2020-01-01,100.0,-2500.0"),
+            URealized::from("2020-02-01,50.0,-1000.0"),
+            URealized::from("2020-03-01,200.0,-6000.0"),
+            URealized::from("2020-04-01,10.0,-200.0"),
+        ];
+        let close = URealized::from("2020-05-01,-50.0,1500.0");
+        assert_eq!(Hifo.select(&lots, &close), 2);
+        // equal unit cost relieves oldest first
+        assert_eq!(Lofo.select(&lots, &close), 1);
+    }
+
+    #[test]
+    fn hifo_and_lofo_select_short_inventory_by_price_received() {
+        let lots = [
+            URealized::from("2020-01-01,-100.0,2500.0"),
+            URealized::from("2020-02-01,-100.0,3000.0"),
+            URealized::from("2020-03-01,-100.0,2000.0"),
+        ];
+        let close = URealized::from("2020-05-01,50.0,-1500.0");
+        assert_eq!(Hifo.select(&lots, &close), 1);
+        assert_eq!(Lofo.select(&lots, &close), 2);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::MARGIN_ERROR_QUANTITY;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+#[cfg(feature = "decimal")]
+pub use rust_decimal::Decimal;
+
+/// Numeric type used for quantities and basis of the core types.
+///
+/// `f64` is the default everywhere.  With the `decimal` feature `rust_decimal::Decimal`, re-exported as
+/// `num::Decimal`, is also a `Number` so money and quantities are exact to 28 significant digits and
+/// cents reconcile with broker statements.
+pub trait Number:
+    Copy
+    + PartialEq
+    + PartialOrd
+    + Default
+    + fmt::Debug
+    + fmt::Display
+    + FromStr
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + Sum
+    + Send
+    + Sync
+    + 'static
+{
+    fn zero() -> Self;
+
+    fn one() -> Self;
+
+    /// Nearest value to `value`, used for constants such as rates
+    fn from_f64(value: f64) -> Self;
+
+    /// Nearest `f64`, used for reporting in an `Error`
+    fn to_f64(self) -> f64;
+
+    fn abs(self) -> Self;
+
+    /// Rounded to `decimals` places
+    fn round_dp(self, decimals: u32) -> Self;
+
+    fn min(self, other: Self) -> Self {
+        if other < self {
+            other
+        } else {
+            self
+        }
+    }
+
+    /// Quantity below which inventory is treated as zero
+    fn margin() -> Self {
+        Self::from_f64(MARGIN_ERROR_QUANTITY)
+    }
+}
+
+impl Number for f64 {
+    fn zero() -> Self {
+        0.0
+    }
+
+    fn one() -> Self {
+        1.0
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn abs(self) -> Self {
+        f64::abs(self)
+    }
+
+    fn round_dp(self, decimals: u32) -> Self {
+        let scale = 10f64.powi(decimals as i32);
+        (self * scale).round() / scale
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl Number for Decimal {
+    fn zero() -> Self {
+        Decimal::ZERO
+    }
+
+    fn one() -> Self {
+        Decimal::ONE
+    }
+
+    fn from_f64(value: f64) -> Self {
+        rust_decimal::prelude::FromPrimitive::from_f64(value).unwrap_or_default()
+    }
+
+    fn to_f64(self) -> f64 {
+        rust_decimal::prelude::ToPrimitive::to_f64(&self).unwrap_or(f64::NAN)
+    }
+
+    fn abs(self) -> Self {
+        Decimal::abs(&self)
+    }
+
+    fn round_dp(self, decimals: u32) -> Self {
+        Decimal::round_dp(&self, decimals)
+    }
+
+    fn margin() -> Self {
+        Decimal::new(1, 10)
+    }
+}
@@ -0,0 +1,240 @@
+use crate::error::Error;
+use crate::holding::Holding;
+use crate::inventory::{Inventory, VolumeSplit};
+use crate::num::Number;
+use crate::realized::{total_realized, Realized};
+use std::collections::BTreeMap;
+
+/// Portfolio of holdings of many assets keyed by an asset identifier such as a symbol.
+///
+/// Each inventory change is routed to the `Holding` of its asset and the `Realized` of every asset are
+/// accumulated.  A holding is created the first time an asset is traded as a copy of the holding the
+/// portfolio was created with, which sets the configuration.  Assets are kept in order of identifier.
+#[derive(Debug, Clone)]
+pub struct Portfolio<N = f64>
+where
+    N: Number,
+{
+    start: Holding<N>,
+    assets: BTreeMap<String, (Holding<N>, Vec<Realized<N>>)>,
+}
+
+impl<N> Default for Portfolio<N>
+where
+    N: Number,
+{
+    fn default() -> Self {
+        Portfolio::new(Holding::default())
+    }
+}
+
+impl<N> Portfolio<N>
+where
+    N: Number,
+{
+    /// Empty portfolio where the holding of each asset starts as a copy of `holding`
+    pub fn new(holding: Holding<N>) -> Self {
+        Portfolio {
+            start: holding,
+            assets: BTreeMap::new(),
+        }
+    }
+
+    /// Adds an inventory change of `asset` to its holding and keeps the realized gains returned.
+    ///
+    /// Panics the same as `Holding::add_transaction`.  Use `try_add_transaction` to handle the error
+    /// instead.
+    pub fn add_transaction<T>(&mut self, asset: &str, inv: &T) -> Vec<Realized<N>>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        match self.try_add_transaction(asset, inv) {
+            Ok(realized) => realized,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Same as `add_transaction` but returns an `Error` when the inventory change can't be matched.
+    ///
+    /// An asset isn't added to the portfolio by an inventory change in error.
+    pub fn try_add_transaction<T>(
+        &mut self,
+        asset: &str,
+        inv: &T,
+    ) -> Result<Vec<Realized<N>>, Error>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        self.try_extend_transactions(asset, std::slice::from_ref(inv))
+    }
+
+    /// Adds a series of inventory changes of `asset` the same as `Holding::extend_transactions`.
+    ///
+    /// Panics the same as `Holding::extend_transactions`.
+    pub fn extend_transactions<T>(&mut self, asset: &str, invs: &[T]) -> Vec<Realized<N>>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        match self.try_extend_transactions(asset, invs) {
+            Ok(realized) => realized,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Same as `extend_transactions` but returns the first `Error` instead of panicking.
+    ///
+    /// Inventory changes before the one in error remain applied to the holding of the asset.
+    pub fn try_extend_transactions<T>(
+        &mut self,
+        asset: &str,
+        invs: &[T],
+    ) -> Result<Vec<Realized<N>>, Error>
+    where
+        T: Inventory<N> + VolumeSplit<T, N> + Copy,
+    {
+        self.extend_with(asset, invs, |holding, inv| holding.try_add_transaction(inv))
+    }
+
+    // same as `try_extend_transactions` adding each change to the holding of `asset` with `add`
+    pub(crate) fn extend_with<T, F>(
+        &mut self,
+        asset: &str,
+        invs: &[T],
+        mut add: F,
+    ) -> Result<Vec<Realized<N>>, Error>
+    where
+        T: Inventory<N> + Copy,
+        F: FnMut(&mut Holding<N>, &T) -> Result<Vec<Realized<N>>, Error>,
+    {
+        let existing = self.assets.remove(asset);
+        let new = existing.is_none();
+        let (mut holding, mut realized) =
+            existing.unwrap_or_else(|| (self.start.clone(), Vec::new()));
+        // realized gains of the changes applied before an error are kept with the holding
+        let mut applied = false;
+        let (gains_r, result) = holding.extend_with(invs, |holding, inv| {
+            let r = add(holding, inv)?;
+            applied = true;
+            Ok(r)
+        });
+        realized.extend(gains_r.iter().cloned());
+        if !new || applied {
+            self.assets.insert(asset.to_owned(), (holding, realized));
+        }
+        result.map(|_| gains_r)
+    }
+
+    /// Asset identifiers in order
+    pub fn assets(&self) -> impl Iterator<Item = &str> {
+        self.assets.keys().map(|a| a.as_str())
+    }
+
+    /// Asset identifiers in order with their holding and all realized gains
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Holding<N>, &[Realized<N>])> {
+        self.assets
+            .iter()
+            .map(|(a, (h, r))| (a.as_str(), h, r.as_slice()))
+    }
+
+    /// Holding of `asset` or `None` if it hasn't been traded
+    pub fn holding(&self, asset: &str) -> Option<&Holding<N>> {
+        self.assets.get(asset).map(|(h, _)| h)
+    }
+
+    /// All realized gains of `asset` in order of the inventory changes
+    pub fn realized(&self, asset: &str) -> &[Realized<N>] {
+        self.assets
+            .get(asset)
+            .map(|(_, r)| r.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Current position of `asset`: `(quantity, price, basis)` or `None` if it hasn't been traded
+    pub fn position(&self, asset: &str) -> Option<(N, N, N)> {
+        self.holding(asset).map(|h| h.position())
+    }
+
+    /// Assets with inventory held and their position `(quantity, price, basis)`
+    pub fn open_positions(&self) -> Vec<(&str, (N, N, N))> {
+        self.iter()
+            .filter(|(_, h, _)| h.direction().is_some())
+            .map(|(a, h, _)| (a, h.position()))
+            .collect()
+    }
+
+    /// Total realized gain/loss of all assets
+    pub fn total_realized(&self) -> N {
+        self.assets.values().map(|(_, r)| total_realized(r)).sum()
+    }
+
+    // replace the holding of `asset` keeping its realized gains
+    pub(crate) fn set_holding(&mut self, asset: &str, holding: Holding<N>) {
+        match self.assets.get_mut(asset) {
+            Some((h, _)) => *h = holding,
+            None => {
+                self.assets.insert(asset.to_owned(), (holding, Vec::new()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::transaction::Transaction;
+    use crate::unrealized::URealized;
+
+    fn set_portfolio() -> Portfolio {
+        let mut portfolio = Portfolio::default();
+        portfolio.add_transaction("ETH", &Transaction::from("2020-01-01,buy,2.0,1000.0"));
+        portfolio.add_transaction("BTC", &Transaction::from("2020-01-02,buy,1.0,8000.0"));
+        portfolio.add_transaction("ETH", &Transaction::from("2020-02-01,sell,1.0,1500.0"));
+        portfolio.add_transaction("BTC", &Transaction::from("2020-03-01,sell,1.0,7000.0"));
+        portfolio
+    }
+
+    #[test]
+    fn transactions_routed_to_holding_of_asset() {
+        let portfolio = set_portfolio();
+        assert_eq!(portfolio.assets().collect::<Vec<_>>(), ["BTC", "ETH"]);
+        assert_eq!(
+            portfolio.realized("ETH"),
+            [Realized::from("2020-02-01,-1.0,1500.0,2020-01-01,-1000.0")]
+        );
+        assert_eq!(
+            portfolio.holding("ETH").unwrap().inventory(),
+            [URealized::from("2020-01-01,1.0,-1000.0")]
+        );
+        assert!(portfolio.realized("XRP").is_empty());
+        assert!(portfolio.position("XRP").is_none());
+    }
+
+    #[test]
+    fn aggregate_realized_and_open_positions() {
+        let portfolio = set_portfolio();
+        assert_eq!(portfolio.total_realized(), -500.0);
+        assert_eq!(
+            portfolio.open_positions(),
+            [("ETH", (1.0, 1000.0, -1000.0))]
+        );
+        assert_eq!(portfolio.position("BTC"), Some((0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn failed_batch_keeps_realized_of_changes_applied() {
+        let mut portfolio = set_portfolio();
+        let batch = [
+            Transaction::from("2020-04-01,buy,1.0,9000.0"),
+            Transaction::from("2020-05-01,sell,1.0,9500.0"),
+            Transaction::from("2020-01-01,buy,1.0,7500.0"),
+        ];
+        assert!(matches!(
+            portfolio.try_extend_transactions("BTC", &batch),
+            Err(Error::OutOfOrder(_, _))
+        ));
+        assert_eq!(portfolio.realized("BTC").len(), 2);
+        assert_eq!(portfolio.total_realized(), 0.0);
+        assert_eq!(portfolio.position("BTC"), Some((0.0, 0.0, 0.0)));
+    }
+}
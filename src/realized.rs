@@ -1,10 +1,14 @@
+use crate::error::{parse_date, parse_field, Error};
+use crate::gain::{GainType, HoldingPeriod};
 use crate::inventory::Inventory;
+use crate::num::Number;
 use crate::unrealized::URealized;
 use chrono::NaiveDate;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::hash::Hash;
-
+use std::str::FromStr;
 
 // TODO: shrink down to one volume for Realized
 
@@ -12,29 +16,97 @@ use std::hash::Hash;
 /// realized gain or loss.
 ///
 /// For now use Display trait to view
-// close date, quantity, close value, open date, open value, realized gain
-#[derive(Debug, PartialEq)]
-pub struct Realized(NaiveDate, f64, f64, NaiveDate, f64, f64);
-
-impl Realized {
-
-    pub fn new(c_date: NaiveDate, quantity: f64, c_basis: f64, o_date: NaiveDate, o_basis: f64) -> Self {
-        Realized {
-            0: c_date,
-            1: quantity,
-            2: c_basis,
-            3: o_date,
-            4: o_basis,
-            5: c_basis + o_basis,
-        }
+// close date, quantity, close value, open date, open value, realized gain, adjustment, match rule,
+// lot id of the inventory closed
+#[derive(Debug, PartialEq, Clone)]
+pub struct Realized<N = f64>(
+    NaiveDate,
+    N,
+    N,
+    NaiveDate,
+    N,
+    N,
+    Option<Adjustment<N>>,
+    Option<MatchRule>,
+    Option<u32>,
+);
+
+/// Rule that matched the close to the open when matching isn't by lot, for example UK share matching
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum MatchRule {
+    /// acquisition on the same day as the disposal
+    SameDay,
+    /// acquisition within the 30 days following the disposal - bed and breakfast rule
+    BedAndBreakfast,
+    /// Section 104 average cost pool
+    Section104,
+}
+
+/// Reason a realized gain/loss was adjusted
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum AdjustmentCode {
+    /// US wash sale, code `W` on form 8949
+    WashSale,
+    /// Canadian superficial loss
+    SuperficialLoss,
+}
+
+/// Adjustment to a realized gain/loss - the amount is added to the gain/loss, so a disallowed loss is
+/// a positive amount
+// code, amount
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Adjustment<N = f64>(AdjustmentCode, N);
+
+impl<N> Adjustment<N>
+where
+    N: Number,
+{
+    pub fn new(code: AdjustmentCode, amount: N) -> Self {
+        Adjustment(code, amount)
     }
 
-    pub fn match_close<T>(inv: &T, inv_ur: &URealized) -> Realized
+    pub fn code(&self) -> AdjustmentCode {
+        self.0
+    }
+
+    pub fn amount(&self) -> N {
+        self.1
+    }
+}
+
+impl<N> Realized<N>
+where
+    N: Number,
+{
+    pub fn new(c_date: NaiveDate, quantity: N, c_basis: N, o_date: NaiveDate, o_basis: N) -> Self {
+        Realized(
+            c_date,
+            quantity,
+            c_basis,
+            o_date,
+            o_basis,
+            c_basis + o_basis,
+            None,
+            None,
+            None,
+        )
+    }
+
+    /// Realized of the close `inv` matched to the open inventory `inv_ur`, the fee of the close reduces
+    /// the proceeds
+    pub fn match_close<T>(inv: &T, inv_ur: &URealized<N>) -> Realized<N>
     where
-        T: Inventory,
+        T: Inventory<N>,
     {
         // todo: include panic if volumes don't match and are in opposite directions
-        Realized::new(inv.date(), inv.quantity(), inv.basis(), inv_ur.date(), inv_ur.basis())
+        Realized::new(
+            inv.date(),
+            inv.quantity(),
+            inv.basis() - inv.fee(),
+            inv_ur.date(),
+            inv_ur.basis(),
+        )
+        .with_lot_id(inv_ur.lot_id())
 
         // Realized {
         //     0: inv.date(),
@@ -50,11 +122,11 @@ impl Realized {
     // make crate private - only holding uses this function
     pub fn zero_profit(&mut self) {
         self.2 = -self.4;
-        self.5 = 0.0;
+        self.5 = N::zero();
     }
     // make crate private - only holding uses this function
     pub fn zero_value(&mut self) {
-        self.2 = 0.0;
+        self.2 = N::zero();
         self.5 = self.4;
     }
 
@@ -62,41 +134,125 @@ impl Realized {
     pub fn close_date(&self) -> NaiveDate {
         self.0
     }
-    pub fn quantity(&self) -> f64 {
+    pub fn quantity(&self) -> N {
         self.1
     }
-    pub fn close_basis(&self) -> f64 {
+    pub fn close_basis(&self) -> N {
         self.2
     }
     pub fn open_date(&self) -> NaiveDate {
         self.3
     }
-    pub fn open_basis(&self) -> f64 {
+    pub fn open_basis(&self) -> N {
         self.4
     }
-    pub fn realized(&self) -> f64 {
+    pub fn realized(&self) -> N {
         self.5
     }
+    pub fn adjustment(&self) -> Option<Adjustment<N>> {
+        self.6
+    }
+    pub fn match_rule(&self) -> Option<MatchRule> {
+        self.7
+    }
+
+    /// Lot id of the inventory closed, if it was tagged with one
+    pub fn lot_id(&self) -> Option<u32> {
+        self.8
+    }
+
+    // same realized gain/loss closing the lot tagged `id`
+    pub(crate) fn with_lot_id(mut self, id: Option<u32>) -> Self {
+        self.8 = id;
+        self
+    }
+
+    // same realized tagged with the rule that matched it
+    pub(crate) fn with_match_rule(mut self, rule: MatchRule) -> Self {
+        self.7 = Some(rule);
+        self
+    }
+
+    // same realized with the cost converted at `open_rate` and proceeds and adjustment at `close_rate`
+    pub(crate) fn converted(&self, open_rate: N, close_rate: N) -> Self {
+        let adjustment = self.6.map(|a| Adjustment(a.0, a.1 * close_rate));
+        let c_basis = self.2 * close_rate;
+        let o_basis = self.4 * open_rate;
+        Realized(
+            self.0,
+            self.1,
+            c_basis,
+            self.3,
+            o_basis,
+            c_basis + o_basis + adjustment.map_or(N::zero(), |a| a.1),
+            adjustment,
+            self.7,
+            self.8,
+        )
+    }
+
+    /// Loss disallowed by an adjustment, zero if there is no adjustment
+    pub fn disallowed(&self) -> N {
+        self.6.map_or(N::zero(), |a| a.amount())
+    }
+
+    // add amount to gain/loss under code, accumulating with an earlier adjustment
+    pub(crate) fn adjust(&mut self, code: AdjustmentCode, amount: N) {
+        self.5 += amount;
+        self.6 = Some(Adjustment(code, self.disallowed() + amount));
+    }
+
+    /// Number of days between open date and close date
+    pub fn holding_period(&self) -> i64 {
+        (self.0 - self.3).num_days()
+    }
+
+    /// Short-term or long-term gain based on the holding period threshold `period`
+    pub fn gain_type(&self, period: HoldingPeriod) -> GainType {
+        period.gain_type(self.3, self.0)
+    }
+}
 
+impl<N> FromStr for Realized<N>
+where
+    N: Number,
+{
+    type Err = Error;
+    /// in the form of `"close_date,quantity,proceeds,open_date,cost"` with an optional `",lot_id"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let field: Vec<&str> = s.split(',').collect();
+        Ok(Realized::new(
+            parse_date(&field, 0, "close date")?,
+            parse_field(&field, 1, "quantity")?,
+            parse_field(&field, 2, "proceeds")?,
+            parse_date(&field, 3, "open date")?,
+            parse_field(&field, 4, "cost")?,
+        )
+        .with_lot_id(match field.get(5) {
+            Some(id) if !id.is_empty() => Some(parse_field(&field, 5, "lot id")?),
+            _ => None,
+        }))
+    }
 }
 
 impl From<&str> for Realized {
+    /// Same form as `FromStr` but panics if the text can't be parsed
     fn from(s: &str) -> Self {
-        let field: Vec<&str> = s.split(',').collect();
-        Realized::new(
-            NaiveDate::parse_from_str(field[0], "%Y-%m-%d").unwrap(),
-            field[1].parse().unwrap(),
-            field[2].parse().unwrap(),
-            NaiveDate::parse_from_str(field[3], "%Y-%m-%d").unwrap(),
-            field[4].parse().unwrap(),
-        )
+        s.parse().unwrap_or_else(|e| panic!("{}", e))
     }
 }
 
-impl fmt::Display for Realized {
+impl<N> fmt::Display for Realized<N>
+where
+    N: Number,
+{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "close_date: {} quantity:{:.4}, proceeds:{:.2}, open_date: {}, cost_basis:{:.2}, gain_loss:{:.2}", 
-            self.0, self.1, self.2, self.3, self.4, self.5)
+            self.0, self.1, self.2, self.3, self.4, self.5)?;
+        match self.6 {
+            Some(a) => write!(f, ", adjustment:{:?} {:.2}", a.0, a.1),
+            None => Ok(()),
+        }
     }
 }
 
@@ -104,22 +260,28 @@ impl fmt::Display for Realized {
 /// close date.  This helps when you have for example one sale which covers multiple buys on a variety of dates.
 // sales date, quantity, proceeds, costs, pl
 #[derive(Debug, PartialEq)]
-pub struct RealizedCompact(NaiveDate, f64, f64, String, f64, f64);
-
-impl RealizedCompact {
-    fn new(date: NaiveDate, quantity: f64, proceeds: f64, open_dates: String, costs: f64) -> Self {
-        Self {
-            0: date,
-            1: quantity,
-            2: proceeds,
-            3: open_dates,
-            4: costs,
-            5: proceeds + costs,
-        }
+pub struct RealizedCompact<N = f64>(NaiveDate, N, N, String, N, N);
+
+impl<N> RealizedCompact<N>
+where
+    N: Number,
+{
+    fn new(date: NaiveDate, quantity: N, proceeds: N, open_dates: String, costs: N) -> Self {
+        Self(
+            date,
+            quantity,
+            proceeds,
+            open_dates,
+            costs,
+            proceeds + costs,
+        )
     }
 }
 
-impl fmt::Display for RealizedCompact {
+impl<N> fmt::Display for RealizedCompact<N>
+where
+    N: Number,
+{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
@@ -129,28 +291,40 @@ impl fmt::Display for RealizedCompact {
     }
 }
 
-impl From<&[Realized]> for RealizedCompact {
-    fn from(realized: &[Realized]) -> Self {
+impl<N> TryFrom<&[Realized<N>]> for RealizedCompact<N>
+where
+    N: Number,
+{
+    type Error = Error;
+    /// `Error::Empty` if there are no realized to combine
+    fn try_from(realized: &[Realized<N>]) -> Result<Self, Self::Error> {
         // need to check that all dates are the same?
-        let date = realized[0].0;
-        let quantity = realized.iter().map(|r| r.1).sum::<f64>().abs();
+        let date = match realized.first() {
+            Some(r) => r.0,
+            None => return Err(Error::Empty("realized to combine".to_owned())),
+        };
+        let quantity = realized.iter().map(|r| r.1).sum::<N>().abs();
         let proceeds = realized.iter().map(|r| r.2).sum();
         // add a string of dates, or insert various
         let costs = realized.iter().map(|r| r.4).sum();
         //TODO - string with multiple dates?
-        Self::new(date, quantity, proceeds, String::from(""), costs)
+        Ok(Self::new(date, quantity, proceeds, String::from(""), costs))
     }
 }
 
-/// Convert slice of `Realized` into compact form by grouping by close date
-pub fn realized_to_compact(realized: &[Realized]) -> Vec<RealizedCompact> {
+/// Convert slice of `Realized` into compact form by grouping by close date, empty if there are no
+/// `Realized`
+pub fn realized_to_compact<N>(realized: &[Realized<N>]) -> Vec<RealizedCompact<N>>
+where
+    N: Number,
+{
     // group by date - assumes slice is ordered
     // strip out column of dates to group by
     let dates: Vec<NaiveDate> = realized.iter().map(|r| r.0).collect();
     let group_index = group_by_index(&dates);
     group_index
         .iter()
-        .map(|i| RealizedCompact::from(&realized[i.0..i.1]))
+        .filter_map(|i| RealizedCompact::try_from(&realized[i.0..i.1]).ok())
         .collect()
 }
 
@@ -159,6 +333,9 @@ fn group_by_index<T>(s: &[T]) -> Vec<(usize, usize)>
 where
     T: Eq + Hash + Copy,
 {
+    if s.is_empty() {
+        return vec![];
+    }
     let mut index: HashMap<T, usize> = HashMap::new();
     for (i, g) in s.iter().enumerate() {
         if !index.contains_key(g) {
@@ -179,10 +356,35 @@ where
 }
 
 /// Total realized is the sum of all profit / loss in the slice of `Realized`
-pub fn total_realized(r: &[Realized]) -> f64 {
+pub fn total_realized<N>(r: &[Realized<N>]) -> N
+where
+    N: Number,
+{
     r.iter().map(|r| r.5).sum()
 }
 
+/// Split slice of `Realized` into short-term and long-term
+pub fn split_by_gain_type<N>(
+    r: &[Realized<N>],
+    period: HoldingPeriod,
+) -> (Vec<Realized<N>>, Vec<Realized<N>>)
+where
+    N: Number,
+{
+    r.iter()
+        .cloned()
+        .partition(|r| r.gain_type(period) == GainType::Shortterm)
+}
+
+/// Total realized of the slice of `Realized` as `(short-term, long-term)`
+pub fn total_realized_by_gain_type<N>(r: &[Realized<N>], period: HoldingPeriod) -> (N, N)
+where
+    N: Number,
+{
+    let (short, long) = split_by_gain_type(r, period);
+    (total_realized(&short), total_realized(&long))
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -202,21 +404,21 @@ mod tests {
     fn given_array_of_realized_group_by_close_date() {
         let result = [
             RealizedCompact::new(
-                NaiveDate::from_ymd(2020, 4, 1),
+                NaiveDate::from_ymd_opt(2020, 4, 1).unwrap(),
                 200.0,
                 7000.0,
                 String::new(),
                 -5000.0,
             ),
             RealizedCompact::new(
-                NaiveDate::from_ymd(2020, 6, 1),
+                NaiveDate::from_ymd_opt(2020, 6, 1).unwrap(),
                 100.0,
                 3500.0,
                 String::new(),
                 -2500.0,
             ),
             RealizedCompact::new(
-                NaiveDate::from_ymd(2020, 7, 1),
+                NaiveDate::from_ymd_opt(2020, 7, 1).unwrap(),
                 200.0,
                 7000.0,
                 String::new(),
@@ -226,8 +428,43 @@ mod tests {
         assert_eq!(realized_to_compact(&set_realized()), result);
     }
 
+    #[test]
+    fn no_realized_to_group() {
+        assert!(realized_to_compact::<f64>(&[]).is_empty());
+        assert_eq!(
+            RealizedCompact::<f64>::try_from(&[][..]),
+            Err(Error::Empty("realized to combine".to_owned()))
+        );
+    }
+
     #[test]
     fn given_array_of_realized_calculate_total_gain() {
         assert_eq!(total_realized(&set_realized()), 5000.0);
     }
+
+    #[test]
+    fn given_array_of_realized_split_by_gain_type() {
+        let realized = [
+            Realized::from("2021-01-01,-100.0,3500.0,2020-01-01,-2500.0"),
+            Realized::from("2021-01-02,-100.0,2000.0,2020-01-01,-2500.0"),
+            Realized::from("2021-01-02,-100.0,4000.0,2020-06-01,-2500.0"),
+        ];
+        assert_eq!(realized[0].holding_period(), 366);
+        assert_eq!(
+            realized[0].gain_type(HoldingPeriod::default()),
+            GainType::Shortterm
+        );
+        assert_eq!(
+            realized[1].gain_type(HoldingPeriod::default()),
+            GainType::Longterm
+        );
+
+        let (short, long) = split_by_gain_type(&realized, HoldingPeriod::default());
+        assert_eq!(short, [realized[0].clone(), realized[2].clone()]);
+        assert_eq!(long, [realized[1].clone()]);
+        assert_eq!(
+            total_realized_by_gain_type(&realized, HoldingPeriod::default()),
+            (2500.0, -500.0)
+        );
+    }
 }
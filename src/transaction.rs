@@ -1,16 +1,20 @@
+use super::error::{parse_date, parse_field, Error};
+use super::fx::Currency;
 use super::inventory::{Inventory, InventoryType, VolumeSplit};
+use super::lot::LotRef;
+use super::num::Number;
 use chrono::NaiveDate;
 use std::str::FromStr;
 
 impl std::str::FromStr for InventoryType {
-    type Err = String;
+    type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "Long" | "long" | "buy" | "BUY" | "b" | "l" => Ok(InventoryType::Long),
             "Short" | "short" | "sell" | "SELL" | "s" => Ok(InventoryType::Short),
             "Receive" | "Transfer_In" | "RECEIVE" | "Add" | "ADD" => Ok(InventoryType::Add),
             "Send" | "Transfer_Out" | "SEND" | "Remove" | "REMOVE" => Ok(InventoryType::Remove),
-            _ => Err(format!("'{}' is not a valid value for InventoryType", s)),
+            _ => Err(Error::Parse("InventoryType".to_owned(), s.to_owned())),
         }
     }
 }
@@ -21,38 +25,111 @@ impl std::str::FromStr for InventoryType {
 ///
 /// User can implement their own Transaction Struct by implementing both Inventory and VolumeSplit
 ///
-// transaction date, transaction type, quantity, price
+/// An optional `LotRef` tags the lot opened or identifies the specific lot closed.  An optional fee is
+/// capitalized into the basis of inventory opened or reduces the proceeds of inventory closed.  An
+/// optional `Currency` is the currency of the price and fee, used by `FxRates` for conversion.
+///
+/// Quantity and price are `f64` unless another `Number` is given, for example `Transaction<Decimal>`
+/// parsed with `str::parse`.
+///
+// transaction date, transaction type, quantity, price, lot reference, fee, currency
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub struct Transaction(NaiveDate, InventoryType, f64, f64);
+pub struct Transaction<N = f64>(
+    NaiveDate,
+    InventoryType,
+    N,
+    N,
+    Option<LotRef>,
+    N,
+    Option<Currency>,
+);
+
+impl<N> FromStr for Transaction<N>
+where
+    N: Number,
+{
+    type Err = Error;
+    /// in the form of `"yyyy-mm-dd,inventory_type,quantity,price"` with an optional `",lot_reference"`
+    /// as `"yyyy-mm-dd"` or `"yyyy-mm-dd#lot_id"`, an optional `",fee"` and an optional `",currency"`,
+    /// for example `"2020-01-01,buy,100.0,25.0,,4.95"` or `"2020-01-01,buy,100.0,25.0,,,EUR"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let field: Vec<&str> = s.split(',').collect();
+        Ok(Transaction(
+            parse_date(&field, 0, "transaction date")?,
+            parse_field(&field, 1, "InventoryType")?,
+            parse_field(&field, 2, "quantity")?,
+            parse_field(&field, 3, "price")?,
+            match field.get(4) {
+                Some(l) if !l.is_empty() => Some(l.parse()?),
+                _ => None,
+            },
+            match field.get(5) {
+                Some(fee) if !fee.is_empty() => parse_field(&field, 5, "fee")?,
+                _ => N::zero(),
+            },
+            match field.get(6) {
+                Some(c) if !c.is_empty() => Some(c.parse()?),
+                _ => None,
+            },
+        ))
+    }
+}
 
 impl From<&str> for Transaction {
-    /// in the form of `"yyyy-mm-dd,inventory_type,quantity,price"`
+    /// Same form as `FromStr` but panics if the text can't be parsed
     fn from(s: &str) -> Self {
-        let field: Vec<&str> = s.split(',').collect();
-        Transaction {
-            0: NaiveDate::parse_from_str(field[0], "%Y-%m-%d").unwrap(),
-            1: InventoryType::from_str(field[1]).unwrap(),
-            2: field[2].parse().unwrap(),
-            3: field[3].parse().unwrap(),
-        }
+        s.parse().unwrap_or_else(|e| panic!("{}", e))
     }
 }
 
-impl Transaction {
-    fn quant_multiplier(&self) -> f64 {
+impl<N> Transaction<N>
+where
+    N: Number,
+{
+    pub fn new(date: NaiveDate, itype: InventoryType, quantity: N, price: N) -> Self {
+        Transaction(date, itype, quantity, price, None, N::zero(), None)
+    }
+
+    /// Same transaction referencing a specific lot
+    pub fn with_lot(self, lot: LotRef) -> Self {
+        Transaction(self.0, self.1, self.2, self.3, Some(lot), self.5, self.6)
+    }
+
+    /// Same transaction paying `fee`
+    pub fn with_fee(self, fee: N) -> Self {
+        Transaction(self.0, self.1, self.2, self.3, self.4, fee, self.6)
+    }
+
+    /// Same transaction with the price and fee in `currency`
+    pub fn with_currency(self, currency: Currency) -> Self {
+        Transaction(
+            self.0,
+            self.1,
+            self.2,
+            self.3,
+            self.4,
+            self.5,
+            Some(currency),
+        )
+    }
+
+    fn quant_multiplier(&self) -> N {
         match self.1 {
-            InventoryType::Long | InventoryType::Add => 1.0,
-            InventoryType::Short | InventoryType::Remove => -1.0,
+            InventoryType::Long | InventoryType::Add => N::one(),
+            InventoryType::Short | InventoryType::Remove => -N::one(),
         }
     }
 }
 
-impl Inventory for Transaction {
-    fn basis(&self) -> f64 {
-        self.2 * self.3 * self.quant_multiplier() * -1.0
+impl<N> Inventory<N> for Transaction<N>
+where
+    N: Number,
+{
+    fn basis(&self) -> N {
+        -(self.2 * self.3 * self.quant_multiplier())
     }
 
-    fn quantity(&self) -> f64 {
+    fn quantity(&self) -> N {
         self.2 * self.quant_multiplier()
     }
 
@@ -63,22 +140,41 @@ impl Inventory for Transaction {
     fn itype(&self) -> InventoryType {
         self.1
     }
+
+    fn lot(&self) -> Option<LotRef> {
+        self.4
+    }
+
+    fn fee(&self) -> N {
+        self.5
+    }
+
+    fn currency(&self) -> Option<Currency> {
+        self.6
+    }
 }
 
-impl VolumeSplit<Transaction> for Transaction {
-    fn split(&self, quantity: f64) -> (Transaction, Transaction) {
-        let split1 = Transaction {
-            0: self.0,
-            1: self.1,
-            2: quantity,
-            3: self.3,
-        };
-        let split2 = Transaction {
-            0: self.0,
-            1: self.1,
-            2: self.2 - quantity,
-            3: self.3,
+impl<N> VolumeSplit<Transaction<N>, N> for Transaction<N>
+where
+    N: Number,
+{
+    /// fee is pro-rated by quantity with the remainder left on the 2nd return
+    fn split(&self, quantity: N) -> (Transaction<N>, Transaction<N>) {
+        let fee = if self.5 == N::zero() {
+            N::zero()
+        } else {
+            self.5 * quantity / self.2
         };
+        let split1 = Transaction(self.0, self.1, quantity, self.3, self.4, fee, self.6);
+        let split2 = Transaction(
+            self.0,
+            self.1,
+            self.2 - quantity,
+            self.3,
+            self.4,
+            self.5 - fee,
+            self.6,
+        );
         (split1, split2)
     }
 }
@@ -112,13 +208,54 @@ mod tests {
         assert_eq!(trans2, result2);
     }
 
+    #[test]
+    fn test_split_transaction_pro_rates_fee() {
+        let trans = Transaction::from("2020-01-01,long,100.0,25.0,,10.0");
+        assert_eq!(trans.fee(), 10.0);
+        assert_eq!(trans.basis(), -2500.0);
+        let (trans1, trans2) = trans.split(25.0);
+        assert_eq!(
+            trans1,
+            Transaction::from("2020-01-01,long,25.0,25.0").with_fee(2.5)
+        );
+        assert_eq!(
+            trans2,
+            Transaction::from("2020-01-01,long,75.0,25.0").with_fee(7.5)
+        );
+    }
+
+    #[test]
+    fn test_transaction_with_lot_reference() {
+        let trans = Transaction::from("2020-03-01,short,100.0,25.0,2020-01-01#2");
+        assert_eq!(trans.lot(), Some(LotRef::from("2020-01-01#2")));
+
+        let trans = Transaction::from("2020-03-01,short,100.0,25.0,");
+        assert_eq!(trans.lot(), None);
+        let trans = trans.with_lot(LotRef::from("2020-01-01"));
+        assert_eq!(trans.lot(), Some(LotRef::from("2020-01-01")));
+    }
+
+    #[test]
+    fn test_transaction_with_currency() {
+        let trans = Transaction::from("2020-03-01,buy,100.0,25.0,,,EUR");
+        assert_eq!(trans.currency(), Some(Currency::from("EUR")));
+        assert_eq!(trans.split(25.0).1.currency(), Some(Currency::from("EUR")));
+        assert_eq!(
+            Transaction::from("2020-03-01,buy,100.0,25.0").with_currency(Currency::from("EUR")),
+            trans
+        );
+        assert!("2020-03-01,buy,100.0,25.0,,,EURO"
+            .parse::<Transaction>()
+            .is_err());
+    }
+
     #[test]
     fn test_transaction_long() {
         let trans = Transaction::from("2020-01-01,long,100.0,25.0");
         assert_eq!(trans.quant_multiplier(), 1.0);
         assert!(trans.quantity() > 0.0);
         assert_eq!(trans.basis(), -2500.0);
-        assert_eq!(trans.date(), NaiveDate::from_ymd(2020, 1, 1));
+        assert_eq!(trans.date(), NaiveDate::from_ymd_opt(2020, 1, 1).unwrap());
         assert_eq!(trans.itype(), InventoryType::Long);
     }
 
@@ -128,7 +265,7 @@ mod tests {
         assert_eq!(trans.quant_multiplier(), -1.0);
         assert!(trans.quantity() < 0.0);
         assert_eq!(trans.basis(), 2000.0);
-        assert_eq!(trans.date(), NaiveDate::from_ymd(2020, 2, 1));
+        assert_eq!(trans.date(), NaiveDate::from_ymd_opt(2020, 2, 1).unwrap());
         assert_eq!(trans.itype(), InventoryType::Short);
     }
 }
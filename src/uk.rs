@@ -0,0 +1,225 @@
+use crate::error::Error;
+use crate::inventory::{Inventory, VolumeSplit};
+use crate::num::Number;
+use crate::realized::{MatchRule, Realized};
+use crate::unrealized::URealized;
+use chrono::{Duration, NaiveDate};
+
+/// Days following a disposal that an acquisition is matched under the bed and breakfast rule
+pub const BED_AND_BREAKFAST_DAYS: i64 = 30;
+
+/// `Realized` matched by `share_match` and the Section 104 pool left after the batch
+pub type ShareMatch<N = f64> = (Vec<Realized<N>>, Option<URealized<N>>);
+
+/// Matches a batch of long inventory changes following the HMRC share matching rules.
+///
+/// Acquisitions and disposals on the same day are each treated as a single transaction.  Each disposal is
+/// matched first against acquisitions on the same day, then against acquisitions in the 30 days
+/// following the disposal, earliest first, and finally against the Section 104 pool at its average cost.
+/// `pool` is the Section 104 pool before the batch.
+///
+/// Returns the `Realized` tagged with the `MatchRule` that matched each part of a disposal in the order
+/// of the disposals, and the Section 104 pool left after the batch.  The pooled lot keeps the open date
+/// of the first acquisition added to an empty pool.
+///
+/// The batch has to be sorted by date and a disposal can't be larger than the holding.
+pub fn share_match<T, N>(pool: Option<URealized<N>>, invs: &[T]) -> Result<ShareMatch<N>, Error>
+where
+    T: Inventory<N>,
+    N: Number,
+{
+    let mut acquisitions: Vec<URealized<N>> = Vec::new();
+    let mut disposals: Vec<URealized<N>> = Vec::new();
+    for pair in invs.windows(2) {
+        if pair[1].date() < pair[0].date() {
+            return Err(Error::OutOfOrder(pair[1].date(), pair[0].date()));
+        }
+    }
+    // same day acquisitions or disposals are combined
+    for inv in invs {
+        let ur = URealized::from(inv);
+        let same_day = if ur.quantity() > N::zero() {
+            &mut acquisitions
+        } else {
+            &mut disposals
+        };
+        match same_day.last_mut() {
+            Some(last) if last.date() == ur.date() => {
+                *last = URealized::new(
+                    ur.date(),
+                    last.quantity() + ur.quantity(),
+                    last.basis() + ur.basis(),
+                )
+            }
+            _ => same_day.push(ur),
+        }
+    }
+
+    let mut matched: Vec<Vec<Realized<N>>> = vec![Vec::new(); disposals.len()];
+
+    // same day rule
+    for (d, disposal) in disposals.iter_mut().enumerate() {
+        if let Some(acquisition) = acquisitions
+            .iter_mut()
+            .find(|a| a.date() == disposal.date())
+        {
+            matched[d].extend(match_rule(disposal, acquisition, MatchRule::SameDay));
+        }
+    }
+
+    // bed and breakfast rule
+    for (d, disposal) in disposals.iter_mut().enumerate() {
+        let first_day = disposal.date();
+        let last_day = first_day + Duration::days(BED_AND_BREAKFAST_DAYS);
+        for acquisition in acquisitions
+            .iter_mut()
+            .filter(|a| a.date() > first_day && a.date() <= last_day)
+        {
+            matched[d].extend(match_rule(
+                disposal,
+                acquisition,
+                MatchRule::BedAndBreakfast,
+            ));
+        }
+    }
+
+    // section 104 pool in date order, acquisitions join the pool before same day disposals
+    let mut pool = pool.unwrap_or_else(|| URealized::new(NaiveDate::MIN, N::zero(), N::zero()));
+    let mut a = 0;
+    for (d, disposal) in disposals.iter_mut().enumerate() {
+        while a < acquisitions.len() && acquisitions[a].date() <= disposal.date() {
+            pool = add_to_pool(pool, &acquisitions[a]);
+            a += 1;
+        }
+        if disposal.quantity().abs() < N::margin() {
+            continue;
+        }
+        if pool.quantity() + N::margin() < disposal.quantity().abs() {
+            return Err(Error::InsufficientHolding(
+                disposal.date(),
+                disposal.quantity().abs().to_f64(),
+                pool.quantity().to_f64(),
+            ));
+        }
+        matched[d].extend(match_rule(disposal, &mut pool, MatchRule::Section104));
+    }
+    for acquisition in acquisitions[a..].iter() {
+        pool = add_to_pool(pool, acquisition);
+    }
+
+    let pool = if pool.quantity() > N::margin() {
+        Some(pool)
+    } else {
+        None
+    };
+    Ok((matched.into_iter().flatten().collect(), pool))
+}
+
+// match as much of the disposal to the acquisition as possible, removing the matched quantity from both
+fn match_rule<N>(
+    disposal: &mut URealized<N>,
+    acquisition: &mut URealized<N>,
+    rule: MatchRule,
+) -> Option<Realized<N>>
+where
+    N: Number,
+{
+    let quantity = disposal.quantity().abs().min(acquisition.quantity());
+    if quantity < N::margin() {
+        return None;
+    }
+    let (close, close_rest) = disposal.split(quantity);
+    let (open, open_rest) = acquisition.split(quantity);
+    *disposal = close_rest;
+    *acquisition = open_rest;
+    Some(Realized::match_close(&close, &open).with_match_rule(rule))
+}
+
+// pool keeps the open date of the first acquisition added while empty
+fn add_to_pool<N>(pool: URealized<N>, acquisition: &URealized<N>) -> URealized<N>
+where
+    N: Number,
+{
+    if acquisition.quantity() < N::margin() {
+        pool
+    } else if pool.quantity() < N::margin() {
+        *acquisition
+    } else {
+        URealized::new(
+            pool.date(),
+            pool.quantity() + acquisition.quantity(),
+            pool.basis() + acquisition.basis(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::transaction::Transaction;
+
+    #[test]
+    fn same_day_then_bed_and_breakfast_then_pool() {
+        let transactions = [
+            Transaction::from("2019-01-01,buy,1000.0,4.0"),
+            Transaction::from("2020-05-01,buy,500.0,5.0"),
+            Transaction::from("2020-06-01,sell,700.0,6.0"),
+            Transaction::from("2020-06-01,buy,100.0,5.5"),
+            Transaction::from("2020-06-20,buy,200.0,5.8"),
+            Transaction::from("2020-08-01,buy,100.0,7.0"),
+        ];
+        let (realized, pool) = share_match(None, &transactions).unwrap();
+
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        assert_eq!(
+            realized,
+            [
+                Realized::new(
+                    date("2020-06-01"),
+                    -100.0,
+                    600.0,
+                    date("2020-06-01"),
+                    -550.0
+                )
+                .with_match_rule(MatchRule::SameDay),
+                Realized::new(
+                    date("2020-06-01"),
+                    -200.0,
+                    1200.0,
+                    date("2020-06-20"),
+                    -1160.0
+                )
+                .with_match_rule(MatchRule::BedAndBreakfast),
+                Realized::new(
+                    date("2020-06-01"),
+                    -400.0,
+                    2400.0,
+                    date("2019-01-01"),
+                    -6500.0 * 400.0 / 1500.0
+                )
+                .with_match_rule(MatchRule::Section104),
+            ]
+        );
+        assert_eq!(
+            pool,
+            Some(URealized::new(
+                date("2019-01-01"),
+                1200.0,
+                -6500.0 * 1100.0 / 1500.0 - 700.0
+            ))
+        );
+    }
+
+    #[test]
+    fn disposal_larger_than_holding_is_an_error() {
+        let transactions = [
+            Transaction::from("2020-01-01,buy,100.0,4.0"),
+            Transaction::from("2020-02-01,sell,150.0,6.0"),
+        ];
+        assert!(matches!(
+            share_match(None, &transactions),
+            Err(Error::InsufficientHolding(_, _, _))
+        ));
+    }
+}
@@ -1,37 +1,100 @@
+use crate::config::BasisSplit;
+use crate::error::{parse_date, parse_field, Error};
 use crate::inventory::{Inventory, InventoryType, VolumeSplit};
+use crate::lot::LotRef;
+use crate::num::Number;
 use chrono::NaiveDate;
 use std::fmt;
+use std::str::FromStr;
 
 /// Holds unrealized inventory.
 ///
 /// Quantity is positive for long and negative for short, value is full basis not just price.
-// open date, open quantity, open value
+/// An optional lot id identifies the lot for specific lot relief.
+// open date, open quantity, open value, lot id
 #[derive(Debug, PartialEq, Clone, Copy)]
-pub struct URealized(NaiveDate, f64, f64);
+pub struct URealized<N = f64>(NaiveDate, N, N, Option<u32>);
+
+impl<N> FromStr for URealized<N>
+where
+    N: Number,
+{
+    type Err = Error;
+    /// in the form of `"yy-mm-dd,quantity,basis"` with an optional `",lot_id"`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let field: Vec<&str> = s.split(',').collect();
+        Ok(URealized(
+            parse_date(&field, 0, "open date")?,
+            parse_field(&field, 1, "quantity")?,
+            parse_field(&field, 2, "basis")?,
+            match field.get(3) {
+                Some(id) if !id.is_empty() => Some(parse_field(&field, 3, "lot id")?),
+                _ => None,
+            },
+        ))
+    }
+}
 
 impl From<&str> for URealized {
-    /// in the form of `"yy-mm-dd,quantity,basis"`
+    /// Same form as `FromStr` but panics if the text can't be parsed
     fn from(s: &str) -> Self {
-        let field: Vec<&str> = s.split(',').collect();
-        URealized {
-            0: NaiveDate::parse_from_str(field[0], "%Y-%m-%d").unwrap(),
-            1: field[1].parse().unwrap(),
-            2: field[2].parse().unwrap(),
-        }
+        s.parse().unwrap_or_else(|e| panic!("{}", e))
     }
 }
 
-impl URealized {
-    pub fn new(date: NaiveDate, quantity: f64, basis: f64) -> Self {
-        URealized {
-            0: date,
-            1: quantity,
-            2: basis,
+impl<N> URealized<N>
+where
+    N: Number,
+{
+    pub fn new(date: NaiveDate, quantity: N, basis: N) -> Self {
+        URealized(date, quantity, basis, None)
+    }
+
+    /// Same inventory tagged with a lot id
+    pub fn with_lot_id(self, id: u32) -> Self {
+        URealized(self.0, self.1, self.2, Some(id))
+    }
+
+    pub fn lot_id(&self) -> Option<u32> {
+        self.3
+    }
+
+    /// Split into the closed portion of `quantity` and the left over inventory, dividing the basis
+    /// following `basis_split`.  Quantity is always positive.
+    pub fn split_basis(
+        &self,
+        mut quantity: N,
+        basis_split: BasisSplit,
+    ) -> (URealized<N>, URealized<N>) {
+        if self.1 < N::zero() {
+            quantity = -quantity;
         }
+        let rest = self.1 - quantity;
+        let closed = self.2 * quantity / self.1;
+        let (closed, left) = match basis_split {
+            BasisSplit::Proportional => (closed, self.2 * rest / self.1),
+            BasisSplit::Remainder => (closed, self.2 - closed),
+            BasisSplit::Rounded(decimals) => {
+                let closed = closed.round_dp(decimals);
+                (closed, self.2 - closed)
+            }
+        };
+        (
+            URealized(self.0, quantity, closed, self.3),
+            URealized(self.0, rest, left, self.3),
+        )
+    }
+
+    // same lot with a changed open date and basis, used for basis and holding period adjustments
+    pub(crate) fn adjusted(self, date: NaiveDate, basis: N) -> Self {
+        URealized(date, self.1, basis, self.3)
     }
 }
 
-impl fmt::Display for URealized {
+impl<N> fmt::Display for URealized<N>
+where
+    N: Number,
+{
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
@@ -44,52 +107,54 @@ impl fmt::Display for URealized {
     }
 }
 
-impl VolumeSplit<URealized> for URealized {
+impl<N> VolumeSplit<URealized<N>, N> for URealized<N>
+where
+    N: Number,
+{
     /// first return is the closed portion and 2nd return is left over inventory
-    fn split(&self, mut quantity: f64) -> (URealized, URealized) {
-        if self.1 < 0.0 {
-            quantity *= -1.0;
-        }
-        let split1 = URealized {
-            0: self.0,
-            1: quantity,
-            2: self.2 * quantity / self.1,
-        };
-        let split2 = URealized {
-            0: self.0,
-            1: self.1 - quantity,
-            2: self.2 * (self.1 - quantity) / self.1,
-        };
-        (split1, split2)
+    ///
+    /// Basis is split with the default `BasisSplit::Remainder` so both portions add up to the basis.
+    fn split(&self, quantity: N) -> (URealized<N>, URealized<N>) {
+        self.split_basis(quantity, BasisSplit::default())
     }
 }
 
-impl<T> From<&T> for URealized
+impl<T, N> From<&T> for URealized<N>
 where
-    T: Inventory,
+    T: Inventory<N>,
+    N: Number,
 {
+    /// fee of the inventory change is added to the cost
     fn from(inv: &T) -> Self {
-        Self {
-            0: inv.date(),
-            1: inv.quantity(),
-            2: inv.basis(),
-        }
+        Self(
+            inv.date(),
+            inv.quantity(),
+            inv.basis() - inv.fee(),
+            inv.lot().and_then(|l| l.id()),
+        )
     }
 }
 
-impl Inventory for URealized {
+impl<N> Inventory<N> for URealized<N>
+where
+    N: Number,
+{
     fn date(&self) -> NaiveDate {
         self.0
     }
 
-    fn quantity(&self) -> f64 {
+    fn quantity(&self) -> N {
         self.1
     }
 
-    fn basis(&self) -> f64 {
+    fn basis(&self) -> N {
         self.2
     }
 
+    fn lot(&self) -> Option<LotRef> {
+        self.3.map(|id| LotRef::new(self.0, Some(id)))
+    }
+
     fn itype(&self) -> InventoryType {
         self.direction_type()
     }
@@ -110,6 +175,19 @@ mod tests {
         assert_eq!(split2, result_inv_remaining);
     }
 
+    #[test]
+    fn split_basis_allocates_remainder_to_left_over_inventory() {
+        let start_ur = URealized::from("2020-01-01,3.0,-100.0");
+        let (closed, left) = start_ur.split_basis(1.0, BasisSplit::Rounded(2));
+        assert_eq!(closed, URealized::from("2020-01-01,1.0,-33.33"));
+        assert_eq!(left, URealized::from("2020-01-01,2.0,-66.67"));
+
+        let (closed, left) = start_ur.split_basis(1.0, BasisSplit::Remainder);
+        assert_eq!(left.basis(), -100.0 - closed.basis());
+        let (_, left) = start_ur.split_basis(1.0, BasisSplit::Proportional);
+        assert_eq!(left.basis(), -100.0 * 2.0 / 3.0);
+    }
+
     #[test]
     fn split_gain_unrealized_into_two_short() {
         let start_ur = URealized::from("2020-01-01,-200.0,5000.0");
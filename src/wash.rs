@@ -0,0 +1,148 @@
+use crate::config::BasisSplit;
+use crate::inventory::Inventory;
+use crate::num::Number;
+use crate::realized::{AdjustmentCode, Realized};
+use crate::unrealized::URealized;
+
+/// Days before and after a sale at a loss that an acquisition replaces the inventory sold
+pub const WASH_SALE_WINDOW: i64 = 30;
+
+/// Applies the US wash sale rule to the losses in `realized` using replacement lots held in `inventory`.
+///
+/// Long inventory closed at a loss is a wash sale when inventory was acquired within 30 days before or
+/// after the close date, other than the lot sold.  The lot sold is the lot with the open date and lot id
+/// of the `Realized`, so lots acquired the same day without a lot id can't be told apart from it.  The
+/// loss is disallowed on the `Realized` with a `WashSale` adjustment and added to the basis of the
+/// replacement lot.  The replacement lot carries over the holding period of the lot sold by moving its
+/// open date earlier.
+///
+/// A replacement lot larger than the quantity sold is split following `basis_split` so only the matched
+/// quantity is adjusted and a replacement smaller than the quantity sold only disallows its share of the
+/// loss.  Each replacement lot replaces one sale, lots already closed and not in `inventory` are not
+/// considered.
+pub fn apply_wash_sales<N>(
+    realized: &mut [Realized<N>],
+    inventory: &mut Vec<URealized<N>>,
+    basis_split: BasisSplit,
+) where
+    N: Number,
+{
+    // replacement lots already matched to a loss
+    let mut used = vec![false; inventory.len()];
+    for r in realized.iter_mut() {
+        // only long inventory closed at a loss that isn't already adjusted
+        if r.realized() >= N::zero() || r.quantity() >= N::zero() || r.adjustment().is_some() {
+            continue;
+        }
+        let sold = r.quantity().abs();
+        let loss = r.realized();
+        let held = r.close_date() - r.open_date();
+        let mut remaining = sold;
+        let mut i = 0;
+        while i < inventory.len() && remaining > N::margin() {
+            let lot = inventory[i];
+            if used[i]
+                || lot.quantity() <= N::zero()
+                || (lot.date() == r.open_date() && lot.lot_id() == r.lot_id())
+                || (lot.date() - r.close_date()).num_days().abs() > WASH_SALE_WINDOW
+            {
+                i += 1;
+                continue;
+            }
+            // split so only the quantity needed replaces the sale
+            if lot.quantity() > remaining + N::margin() {
+                let (matched, rest) = lot.split_basis(remaining, basis_split);
+                inventory[i] = matched;
+                inventory.insert(i + 1, rest);
+                used.insert(i + 1, false);
+            }
+            let replacement = inventory[i];
+            let disallowed = -loss * replacement.quantity() / sold;
+            inventory[i] =
+                replacement.adjusted(replacement.date() - held, replacement.basis() - disallowed);
+            used[i] = true;
+            r.adjust(AdjustmentCode::WashSale, disallowed);
+            remaining -= replacement.quantity();
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn loss_disallowed_and_added_to_replacement() {
+        let mut realized = [Realized::from(
+            "2020-02-01,-100.0,4000.0,2020-01-01,-5000.0",
+        )];
+        let mut inventory = vec![URealized::from("2020-02-15,60.0,-2520.0")];
+
+        apply_wash_sales(&mut realized, &mut inventory, BasisSplit::default());
+
+        assert_eq!(realized[0].realized(), -400.0);
+        assert_eq!(realized[0].disallowed(), 600.0);
+        assert_eq!(
+            realized[0].adjustment().map(|a| a.code()),
+            Some(AdjustmentCode::WashSale)
+        );
+        // holding period of 31 days carried over
+        assert_eq!(inventory, [URealized::from("2020-01-15,60.0,-3120.0")]);
+    }
+
+    #[test]
+    fn gains_and_acquisitions_outside_window_are_not_washed() {
+        let mut realized = [
+            Realized::from("2020-02-01,-100.0,6000.0,2020-01-01,-5000.0"),
+            Realized::from("2020-06-01,-100.0,4000.0,2020-01-01,-5000.0"),
+        ];
+        let mut inventory = vec![
+            URealized::from("2020-01-01,100.0,-5000.0"),
+            URealized::from("2020-02-15,100.0,-4200.0"),
+            URealized::from("2020-07-02,100.0,-4200.0"),
+        ];
+        let starting = inventory.clone();
+
+        apply_wash_sales(&mut realized, &mut inventory, BasisSplit::default());
+
+        assert_eq!(realized[0].adjustment(), None);
+        assert_eq!(realized[1].adjustment(), None);
+        assert_eq!(inventory, starting);
+    }
+
+    #[test]
+    fn same_day_lot_with_other_lot_id_replaces_lot_sold() {
+        let mut realized = [Realized::from(
+            "2020-01-20,-100.0,4000.0,2020-01-01,-5000.0,1",
+        )];
+        let mut inventory = vec![URealized::from("2020-01-01,100.0,-5000.0,2")];
+
+        apply_wash_sales(&mut realized, &mut inventory, BasisSplit::default());
+
+        assert_eq!(realized[0].disallowed(), 1000.0);
+        assert_eq!(inventory, [URealized::from("2019-12-13,100.0,-6000.0,2")]);
+
+        // without lot ids the same day lot is taken as the lot sold
+        let mut realized = [Realized::from(
+            "2020-01-20,-100.0,4000.0,2020-01-01,-5000.0",
+        )];
+        let mut inventory = vec![URealized::from("2020-01-01,100.0,-5000.0")];
+        apply_wash_sales(&mut realized, &mut inventory, BasisSplit::default());
+        assert_eq!(realized[0].adjustment(), None);
+    }
+
+    #[test]
+    fn replacement_lot_split_following_basis_split() {
+        let mut realized = [Realized::from("2020-02-01,-1.0,20.0,2020-01-01,-40.0")];
+        let mut inventory = vec![URealized::from("2020-02-15,3.0,-100.0")];
+
+        apply_wash_sales(&mut realized, &mut inventory, BasisSplit::Rounded(2));
+
+        assert_eq!(realized[0].disallowed(), 20.0);
+        assert_eq!(inventory[0].quantity(), 1.0);
+        assert!((inventory[0].basis() - -53.33).abs() < 1e-9);
+        assert_eq!(inventory[1], URealized::from("2020-02-15,2.0,-66.67"));
+    }
+}
@@ -1,6 +1,14 @@
-use costbasis::holding::Holding;
-use costbasis::inventory::InventoryType;
-use costbasis::realized::Realized;
+use chrono::NaiveDate;
+use costbasis::account::Accounts;
+use costbasis::config::{DateOrder, DustTreatment, RemovalTreatment};
+use costbasis::error::Error;
+use costbasis::fx::{Currency, FxRates};
+use costbasis::holding::{DustEvent, Holding};
+use costbasis::inventory::{Inventory, InventoryType};
+use costbasis::ledger::Ledger;
+use costbasis::lot::{AverageCost, Hifo, Lifo, Lofo, LotRef, LotSelector};
+use costbasis::portfolio::Portfolio;
+use costbasis::realized::{MatchRule, Realized};
 use costbasis::transaction::Transaction;
 use costbasis::unrealized::URealized;
 
@@ -176,8 +184,10 @@ fn transactions_that_include_remove_transfers_with_zero_gain() {
 
 #[test]
 fn remove_inventory_with_zerogain_show_output() {
-    let mut holding = Holding::default();
-    holding.add_config("REALIZED_REMOVED_VALUE_AT_COST");
+    let mut holding = Holding::builder()
+        .removal(RemovalTreatment::AtCost)
+        .build()
+        .unwrap();
     let transactions = [
         Transaction::from("2020-03-01,long,100.0,20.0"),
         Transaction::from("2020-04-01,Receive,100.0,25.0"),
@@ -195,9 +205,7 @@ fn remove_inventory_with_zerogain_show_output() {
     let gains_r = holding.add_transaction(&transactions[3]);
     assert_eq!(
         gains_r,
-        vec!(Realized::from(
-            "2020-06-01,-50.0,1000.0,2020-03-01,-1000.0"
-        ))
+        vec!(Realized::from("2020-06-01,-50.0,1000.0,2020-03-01,-1000.0"))
     );
 
     // larger send
@@ -214,9 +222,7 @@ fn remove_inventory_with_zerogain_show_output() {
     let gains_r = holding.add_transaction(&transactions[5]);
     assert_eq!(
         gains_r,
-        vec!(Realized::from(
-            "2020-08-01,-50.0,1250.0,2020-04-01,-1250.0"
-        ))
+        vec!(Realized::from("2020-08-01,-50.0,1250.0,2020-04-01,-1250.0"))
     );
 
     assert_eq!(holding.inventory(), results_ur);
@@ -224,8 +230,10 @@ fn remove_inventory_with_zerogain_show_output() {
 
 #[test]
 fn remove_inventory_with_gain_at_market() {
-    let mut holding = Holding::default();
-    holding.add_config("REMOVED_VALUE_AT_MARKET");
+    let mut holding = Holding::builder()
+        .removal(RemovalTreatment::AtMarket)
+        .build()
+        .unwrap();
     let transactions = [
         Transaction::from("2020-03-01,long,100.0,20.0"),
         Transaction::from("2020-04-01,Receive,100.0,25.0"),
@@ -243,9 +251,7 @@ fn remove_inventory_with_gain_at_market() {
     let gains_r = holding.add_transaction(&transactions[3]);
     assert_eq!(
         gains_r,
-        vec!(Realized::from(
-            "2020-06-01,-50.0,1750.0,2020-03-01,-1000.0"
-        ))
+        vec!(Realized::from("2020-06-01,-50.0,1750.0,2020-03-01,-1000.0"))
     );
 
     // larger send
@@ -262,9 +268,7 @@ fn remove_inventory_with_gain_at_market() {
     let gains_r = holding.add_transaction(&transactions[5]);
     assert_eq!(
         gains_r,
-        vec!(Realized::from(
-            "2020-08-01,-50.0,1750.0,2020-04-01,-1250.0"
-        ))
+        vec!(Realized::from("2020-08-01,-50.0,1750.0,2020-04-01,-1250.0"))
     );
 
     assert_eq!(holding.inventory(), results_ur);
@@ -272,8 +276,10 @@ fn remove_inventory_with_gain_at_market() {
 
 #[test]
 fn remove_inventory_with_gain_at_zero_value() {
-    let mut holding = Holding::default();
-    holding.add_config("REMOVED_VALUE_AT_ZERO");
+    let mut holding = Holding::builder()
+        .removal(RemovalTreatment::AtZero)
+        .build()
+        .unwrap();
     let transactions = [
         Transaction::from("2020-03-01,long,100.0,20.0"),
         Transaction::from("2020-04-01,Receive,100.0,25.0"),
@@ -291,9 +297,7 @@ fn remove_inventory_with_gain_at_zero_value() {
     let gains_r = holding.add_transaction(&transactions[3]);
     assert_eq!(
         gains_r,
-        vec!(Realized::from(
-            "2020-06-01,-50.0,0.0,2020-03-01,-1000.0"
-        ))
+        vec!(Realized::from("2020-06-01,-50.0,0.0,2020-03-01,-1000.0"))
     );
 
     // larger send
@@ -310,10 +314,997 @@ fn remove_inventory_with_gain_at_zero_value() {
     let gains_r = holding.add_transaction(&transactions[5]);
     assert_eq!(
         gains_r,
-        vec!(Realized::from(
-            "2020-08-01,-50.0,0.0,2020-04-01,-1250.0"
+        vec!(Realized::from("2020-08-01,-50.0,0.0,2020-04-01,-1250.0"))
+    );
+
+    assert_eq!(holding.inventory(), results_ur);
+}
+
+#[test]
+fn lifo_buy_sell_realized_and_unrealized_starting_with_multiple_unrealized() {
+    let starting_ur = [
+        URealized::from("2020-01-01,100.0,-2000.0"),
+        URealized::from("2020-02-01,100.0,-2500.0"),
+        URealized::from("2020-03-01,100.0,-3000.0"),
+    ];
+    let mut holding = Holding::with_selector(Lifo);
+    holding.extend_transactions(&starting_ur);
+
+    let transaction = Transaction::from("2020-04-01,short,250.0,35.0");
+    // most recent inventory is closed first leaving part of the oldest
+    let results_ur = [URealized::from("2020-01-01,50.0,-1000.0")];
+    let results_r = [
+        Realized::from("2020-04-01,-100.0,3500.0,2020-03-01,-3000.0"),
+        Realized::from("2020-04-01,-100.0,3500.0,2020-02-01,-2500.0"),
+        Realized::from("2020-04-01,-50.0,1750.0,2020-01-01,-1000.0"),
+    ];
+
+    let gains_r = holding.add_transaction(&transaction);
+    assert_eq!(gains_r, results_r);
+    assert_eq!(holding.inventory(), results_ur);
+}
+
+#[test]
+fn lifo_open_close_more_than_once_zero_balance_twice() {
+    let starting_ur = [
+        URealized::from("2020-01-01,100.0,-2500.0"),
+        URealized::from("2020-02-01,100.0,-3000.0"),
+    ];
+    let mut holding = Holding::with_selector(Lifo);
+    holding.extend_transactions(&starting_ur);
+    let transactions = [
+        Transaction::from("2020-04-01,short,150.0,35.0"),
+        Transaction::from("2020-05-01,long,100.0,25.0"),
+        Transaction::from("2020-06-01,short,150.0,35.0"),
+    ];
+    let results_r = [
+        Realized::from("2020-04-01,-100.0,3500.0,2020-02-01,-3000.0"),
+        Realized::from("2020-04-01,-50.0,1750.0,2020-01-01,-1250.0"),
+        Realized::from("2020-06-01,-100.0,3500.0,2020-05-01,-2500.0"),
+        Realized::from("2020-06-01,-50.0,1750.0,2020-01-01,-1250.0"),
+    ];
+
+    let gains_r = holding.extend_transactions(&transactions);
+    assert_eq!(gains_r, results_r);
+    assert!(holding.inventory().is_empty());
+    assert_eq!(holding.direction(), None);
+}
+
+#[test]
+fn lifo_remove_inventory_with_gain_at_market() {
+    let mut holding = Holding::builder()
+        .selector(Lifo)
+        .removal(RemovalTreatment::AtMarket)
+        .build()
+        .unwrap();
+    let transactions = [
+        Transaction::from("2020-03-01,long,100.0,20.0"),
+        Transaction::from("2020-04-01,Receive,100.0,25.0"),
+        Transaction::from("2020-05-01,long,100.0,30.0"),
+        Transaction::from("2020-06-01,Send,50.0,35.0"),
+        Transaction::from("2020-07-01,Send,100.0,35.0"),
+        Transaction::from("2020-08-01,Send,50.0,35.0"),
+    ];
+    let results_ur = [URealized::from("2020-03-01,100.0,-2000.0")];
+
+    let gains_r = holding.extend_transactions(&transactions[0..=2]);
+    assert!(gains_r.is_empty());
+
+    // partial send
+    let gains_r = holding.add_transaction(&transactions[3]);
+    assert_eq!(
+        gains_r,
+        vec!(Realized::from("2020-06-01,-50.0,1750.0,2020-05-01,-1500.0"))
+    );
+
+    // larger send
+    let gains_r = holding.add_transaction(&transactions[4]);
+    assert_eq!(
+        gains_r,
+        vec!(
+            Realized::from("2020-07-01,-50.0,1750.0,2020-05-01,-1500.0"),
+            Realized::from("2020-07-01,-50.0,1750.0,2020-04-01,-1250.0"),
+        )
+    );
+
+    // equal send
+    let gains_r = holding.add_transaction(&transactions[5]);
+    assert_eq!(
+        gains_r,
+        vec!(Realized::from("2020-08-01,-50.0,1750.0,2020-04-01,-1250.0"))
+    );
+
+    assert_eq!(holding.inventory(), results_ur);
+}
+
+// user defined selection relieving the largest lot first
+#[derive(Debug)]
+struct LargestFirst;
+
+impl LotSelector for LargestFirst {
+    fn select(&self, lots: &[URealized], _close: &URealized) -> usize {
+        let mut largest = 0;
+        for (i, lot) in lots.iter().enumerate() {
+            if lot.quantity().abs() > lots[largest].quantity().abs() {
+                largest = i;
+            }
+        }
+        largest
+    }
+}
+
+#[test]
+fn user_defined_lot_selector() {
+    let mut holding = Holding::with_selector(LargestFirst);
+    let transactions = [
+        Transaction::from("2020-01-01,long,100.0,20.0"),
+        Transaction::from("2020-02-01,long,300.0,25.0"),
+        Transaction::from("2020-03-01,long,200.0,30.0"),
+        Transaction::from("2020-04-01,short,400.0,35.0"),
+    ];
+    let results_r = [
+        Realized::from("2020-04-01,-300.0,10500.0,2020-02-01,-7500.0"),
+        Realized::from("2020-04-01,-100.0,3500.0,2020-03-01,-3000.0"),
+    ];
+    let results_ur = [
+        URealized::from("2020-01-01,100.0,-2000.0"),
+        URealized::from("2020-03-01,100.0,-3000.0"),
+    ];
+
+    let gains_r = holding.extend_transactions(&transactions);
+    assert_eq!(gains_r, results_r);
+    assert_eq!(holding.inventory(), results_ur);
+}
+
+// user defined selection that fails once a single lot is left
+#[derive(Debug)]
+struct FirstOfMany;
+
+impl LotSelector for FirstOfMany {
+    fn select(&self, lots: &[URealized], _close: &URealized) -> usize {
+        if lots.len() > 1 {
+            0
+        } else {
+            lots.len()
+        }
+    }
+}
+
+#[test]
+fn failed_change_relieving_many_lots_leaves_inventory_unchanged() {
+    let mut holding = Holding::with_selector(FirstOfMany);
+    let transactions = [
+        Transaction::from("2020-01-01,long,100.0,20.0"),
+        Transaction::from("2020-02-01,long,100.0,25.0"),
+    ];
+    holding.extend_transactions(&transactions);
+
+    assert_eq!(
+        holding.try_add_transaction(&Transaction::from("2020-03-01,short,150.0,30.0")),
+        Err(Error::LotIndex(1, 1))
+    );
+    assert_eq!(
+        holding.inventory(),
+        [
+            URealized::from("2020-01-01,100.0,-2000.0"),
+            URealized::from("2020-02-01,100.0,-2500.0"),
+        ]
+    );
+    assert_eq!(holding.last_date(), Some(transactions[1].date()));
+}
+
+#[test]
+fn average_cost_pools_inventory_long() {
+    let mut holding = Holding::with_selector(AverageCost);
+    let transactions = [
+        Transaction::from("2020-01-01,long,100.0,20.0"),
+        Transaction::from("2020-02-01,long,100.0,30.0"),
+        Transaction::from("2020-03-01,short,50.0,35.0"),
+        Transaction::from("2020-04-01,long,50.0,45.0"),
+        Transaction::from("2020-05-01,short,200.0,40.0"),
+    ];
+
+    let gains_r = holding.extend_transactions(&transactions[0..=1]);
+    assert!(gains_r.is_empty());
+    assert_eq!(
+        holding.inventory(),
+        [URealized::from("2020-01-01,200.0,-5000.0")]
+    );
+    assert_eq!(holding.position(), (200.0, 25.0, -5000.0));
+
+    // sale relieved at the average price
+    let gains_r = holding.add_transaction(&transactions[2]);
+    assert_eq!(
+        gains_r,
+        [Realized::from("2020-03-01,-50.0,1750.0,2020-01-01,-1250.0")]
+    );
+    assert_eq!(holding.position(), (150.0, 25.0, -3750.0));
+
+    // purchase moves the average price
+    holding.add_transaction(&transactions[3]);
+    assert_eq!(holding.position(), (200.0, 30.0, -6000.0));
+
+    let gains_r = holding.add_transaction(&transactions[4]);
+    assert_eq!(
+        gains_r,
+        [Realized::from(
+            "2020-05-01,-200.0,8000.0,2020-01-01,-6000.0"
+        )]
+    );
+    assert!(holding.inventory().is_empty());
+    assert_eq!(holding.direction(), None);
+}
+
+#[test]
+fn average_cost_pools_inventory_short() {
+    let mut holding = Holding::with_selector(AverageCost);
+    let transactions = [
+        Transaction::from("2020-01-01,short,100.0,30.0"),
+        Transaction::from("2020-02-01,short,100.0,20.0"),
+        Transaction::from("2020-03-01,long,50.0,22.0"),
+    ];
+
+    let gains_r = holding.extend_transactions(&transactions);
+    assert_eq!(
+        gains_r,
+        [Realized::from("2020-03-01,50.0,-1100.0,2020-01-01,1250.0")]
+    );
+    assert_eq!(
+        holding.inventory(),
+        [URealized::from("2020-01-01,-150.0,3750.0")]
+    );
+    assert_eq!(holding.position(), (-150.0, 25.0, 3750.0));
+    assert_eq!(holding.direction(), Some(InventoryType::Short));
+}
+
+#[test]
+fn hifo_relieves_highest_cost_first() {
+    let mut holding = Holding::with_selector(Hifo);
+    let transactions = [
+        Transaction::from("2020-01-01,long,100.0,20.0"),
+        Transaction::from("2020-02-01,long,100.0,30.0"),
+        Transaction::from("2020-03-01,long,100.0,25.0"),
+        Transaction::from("2020-04-01,short,150.0,35.0"),
+    ];
+    let results_r = [
+        Realized::from("2020-04-01,-100.0,3500.0,2020-02-01,-3000.0"),
+        Realized::from("2020-04-01,-50.0,1750.0,2020-03-01,-1250.0"),
+    ];
+    let results_ur = [
+        URealized::from("2020-01-01,100.0,-2000.0"),
+        URealized::from("2020-03-01,50.0,-1250.0"),
+    ];
+
+    let gains_r = holding.extend_transactions(&transactions);
+    assert_eq!(gains_r, results_r);
+    assert_eq!(holding.inventory(), results_ur);
+}
+
+#[test]
+fn lofo_relieves_lowest_cost_first() {
+    let mut holding = Holding::with_selector(Lofo);
+    let transactions = [
+        Transaction::from("2020-01-01,long,100.0,20.0"),
+        Transaction::from("2020-02-01,long,100.0,30.0"),
+        Transaction::from("2020-03-01,long,100.0,25.0"),
+        Transaction::from("2020-04-01,short,150.0,35.0"),
+    ];
+    let results_r = [
+        Realized::from("2020-04-01,-100.0,3500.0,2020-01-01,-2000.0"),
+        Realized::from("2020-04-01,-50.0,1750.0,2020-03-01,-1250.0"),
+    ];
+    let results_ur = [
+        URealized::from("2020-02-01,100.0,-3000.0"),
+        URealized::from("2020-03-01,50.0,-1250.0"),
+    ];
+
+    let gains_r = holding.extend_transactions(&transactions);
+    assert_eq!(gains_r, results_r);
+    assert_eq!(holding.inventory(), results_ur);
+}
+
+#[test]
+fn specific_lot_closed_by_lot_reference() {
+    let mut holding = Holding::default();
+    let transactions = [
+        Transaction::from("2020-01-01,long,100.0,20.0,2020-01-01#1"),
+        Transaction::from("2020-01-01,long,100.0,22.0,2020-01-01#2"),
+        Transaction::from("2020-02-01,long,100.0,30.0"),
+        Transaction::from("2020-03-01,short,100.0,35.0,2020-02-01"),
+        Transaction::from("2020-04-01,short,60.0,35.0,2020-01-01#2"),
+    ];
+    let results_r = [
+        Realized::from("2020-03-01,-100.0,3500.0,2020-02-01,-3000.0"),
+        Realized::from("2020-04-01,-60.0,2100.0,2020-01-01,-1320.0,2"),
+    ];
+    let results_ur = [
+        URealized::from("2020-01-01,100.0,-2000.0,1"),
+        URealized::from("2020-01-01,40.0,-880.0,2"),
+    ];
+
+    let gains_r = holding.extend_transactions(&transactions);
+    assert_eq!(gains_r, results_r);
+    assert_eq!(holding.inventory(), results_ur);
+}
+
+#[test]
+fn specific_lot_errors_when_lot_cant_be_relieved() {
+    let starting_ur = [
+        URealized::from("2020-01-01,100.0,-2000.0"),
+        URealized::from("2020-02-01,100.0,-3000.0"),
+    ];
+    let mut holding = Holding::from(&starting_ur[..]);
+
+    // lot not held
+    let sale = Transaction::from("2020-03-01,short,50.0,35.0").with_lot(LotRef::from("2020-01-15"));
+    assert_eq!(
+        holding.try_add_transaction(&sale),
+        Err(Error::LotNotFound(LotRef::from("2020-01-15")))
+    );
+
+    // lot too small
+    let sale = Transaction::from("2020-03-01,short,150.0,35.0,2020-02-01");
+    assert_eq!(
+        holding.try_add_transaction(&sale),
+        Err(Error::InsufficientLot(
+            LotRef::from("2020-02-01"),
+            100.0,
+            150.0
         ))
     );
 
+    assert_eq!(holding.inventory(), starting_ur);
+}
+
+#[test]
+#[should_panic]
+fn specific_lot_not_held_panics_when_added() {
+    let mut holding = Holding::new(&Transaction::from("2020-01-01,long,100.0,20.0"));
+    holding.add_transaction(&Transaction::from("2020-03-01,short,50.0,35.0,2020-01-02"));
+}
+
+#[test]
+fn parse_errors_instead_of_panic() {
+    assert!("2020-01-01,long,100.0,25.0".parse::<Transaction>().is_ok());
+    assert_eq!(
+        "2020-01-01,hold,100.0,25.0".parse::<Transaction>(),
+        Err(Error::Parse("InventoryType".to_owned(), "hold".to_owned()))
+    );
+    assert_eq!(
+        "2020-13-01,long,100.0,25.0".parse::<Transaction>(),
+        Err(Error::Parse(
+            "transaction date".to_owned(),
+            "2020-13-01".to_owned()
+        ))
+    );
+    assert_eq!(
+        "2020-01-01,long,100.0".parse::<Transaction>(),
+        Err(Error::Parse("price".to_owned(), "".to_owned()))
+    );
+    assert!("2020-01-01,100.0,x".parse::<URealized>().is_err());
+    assert!("2020-02-01,-100.0,3500.0,2020-01-01"
+        .parse::<Realized>()
+        .is_err());
+}
+
+#[test]
+fn try_extend_transactions_stops_at_first_error() {
+    let mut holding = Holding::default();
+    let transactions = [
+        Transaction::from("2020-01-01,long,100.0,20.0"),
+        Transaction::from("2020-02-01,short,50.0,35.0"),
+        Transaction::from("2020-03-01,short,50.0,35.0,2020-02-01"),
+        Transaction::from("2020-04-01,short,50.0,35.0"),
+    ];
+
+    assert_eq!(
+        holding.try_extend_transactions(&transactions[0..2]),
+        Ok(vec![Realized::from(
+            "2020-02-01,-50.0,1750.0,2020-01-01,-1000.0"
+        )])
+    );
+    assert_eq!(
+        holding.try_extend_transactions(&transactions[2..]),
+        Err(Error::LotNotFound(LotRef::from("2020-02-01")))
+    );
+    assert_eq!(
+        holding.inventory(),
+        [URealized::from("2020-01-01,50.0,-1000.0")]
+    );
+}
+
+#[test]
+fn out_of_order_transaction_rejected() {
+    let mut holding = Holding::default();
+    let transactions = [
+        Transaction::from("2020-01-01,long,100.0,20.0"),
+        Transaction::from("2020-03-01,short,50.0,35.0"),
+        Transaction::from("2020-02-01,long,100.0,25.0"),
+    ];
+
+    holding.extend_transactions(&transactions[0..2]);
+    assert_eq!(
+        holding.try_add_transaction(&transactions[2]),
+        Err(Error::OutOfOrder(
+            transactions[2].date(),
+            transactions[1].date()
+        ))
+    );
+    assert_eq!(holding.last_date(), Some(transactions[1].date()));
+    assert_eq!(
+        holding.inventory(),
+        [URealized::from("2020-01-01,50.0,-1000.0")]
+    );
+}
+
+#[test]
+#[should_panic]
+fn out_of_order_batch_panics_when_extended() {
+    let mut holding = Holding::default();
+    holding.extend_transactions(&[
+        Transaction::from("2020-02-01,long,100.0,20.0"),
+        Transaction::from("2020-01-01,long,100.0,25.0"),
+    ]);
+}
+
+#[test]
+fn sort_batch_applies_transactions_in_date_order() {
+    let mut holding = Holding::builder()
+        .date_order(DateOrder::SortBatch)
+        .build()
+        .unwrap();
+    let transactions = [
+        Transaction::from("2020-03-01,short,150.0,35.0"),
+        Transaction::from("2020-02-01,long,100.0,25.0"),
+        Transaction::from("2020-03-01,long,100.0,30.0"),
+        Transaction::from("2020-01-01,long,100.0,20.0"),
+    ];
+    // same day buy is applied before the sell
+    let results_r = [
+        Realized::from("2020-03-01,-100.0,3500.0,2020-01-01,-2000.0"),
+        Realized::from("2020-03-01,-50.0,1750.0,2020-02-01,-1250.0"),
+    ];
+    let results_ur = [
+        URealized::from("2020-02-01,50.0,-1250.0"),
+        URealized::from("2020-03-01,100.0,-3000.0"),
+    ];
+
+    let gains_r = holding.extend_transactions(&transactions);
+    assert_eq!(gains_r, results_r);
     assert_eq!(holding.inventory(), results_ur);
+
+    // a later batch still can't be dated before what has been applied
+    assert_eq!(
+        holding.try_extend_transactions(&[Transaction::from("2020-02-15,long,10.0,20.0")]),
+        Err(Error::OutOfOrder(
+            Transaction::from("2020-02-15,long,10.0,20.0").date(),
+            transactions[0].date()
+        ))
+    );
+}
+
+#[test]
+fn year_end_snapshot_from_history() {
+    let transactions = [
+        Transaction::from("2023-06-01,long,100.0,20.0"),
+        Transaction::from("2023-11-01,short,40.0,30.0"),
+        Transaction::from("2024-01-15,short,60.0,25.0"),
+    ];
+    let ledger = Ledger::from_transactions(Holding::default(), &transactions).unwrap();
+    let year_end = NaiveDate::from_ymd_opt(2023, 12, 31).unwrap();
+
+    let snapshot = ledger.as_of(year_end);
+    assert_eq!(
+        snapshot.inventory,
+        [URealized::from("2023-06-01,60.0,-1200.0")]
+    );
+    assert_eq!(snapshot.position, (60.0, 20.0, -1200.0));
+    assert_eq!(
+        snapshot.realized,
+        [Realized::from("2023-11-01,-40.0,1200.0,2023-06-01,-800.0")]
+    );
+    assert_eq!(ledger.realized().len(), 2);
+}
+
+#[test]
+fn wash_sale_with_partial_replacements() {
+    let mut holding = Holding::default();
+    let transactions = [
+        Transaction::from("2020-01-01,long,100.0,50.0"),
+        Transaction::from("2020-01-20,long,50.0,45.0"),
+        Transaction::from("2020-02-01,short,100.0,40.0"),
+        Transaction::from("2020-02-10,long,100.0,42.0"),
+    ];
+    let mut gains_r = holding.extend_transactions(&transactions);
+    holding.apply_wash_sales(&mut gains_r);
+
+    assert_eq!(gains_r.len(), 1);
+    assert_eq!(gains_r[0].realized(), 0.0);
+    assert_eq!(gains_r[0].disallowed(), 1000.0);
+    // replacement lots carry the disallowed loss and holding period, the unused part is split off
+    assert_eq!(
+        holding.inventory(),
+        [
+            URealized::from("2019-12-20,50.0,-2750.0"),
+            URealized::from("2020-01-10,50.0,-2600.0"),
+            URealized::from("2020-02-10,50.0,-2100.0"),
+        ]
+    );
+    assert_eq!(holding.position().2, -7450.0);
+}
+
+#[test]
+fn uk_share_matching_batch() {
+    let mut holding = Holding::new(&Transaction::from("2019-01-01,long,1000.0,4.0"));
+    let transactions = [
+        Transaction::from("2020-03-01,short,200.0,6.0"),
+        Transaction::from("2020-03-15,long,150.0,5.0"),
+        Transaction::from("2020-05-01,short,100.0,3.0"),
+    ];
+    let gains_r = holding.extend_transactions_uk(&transactions).unwrap();
+
+    let rules: Vec<Option<MatchRule>> = gains_r.iter().map(|r| r.match_rule()).collect();
+    assert_eq!(
+        rules,
+        [
+            Some(MatchRule::BedAndBreakfast),
+            Some(MatchRule::Section104),
+            Some(MatchRule::Section104),
+        ]
+    );
+    // bed and breakfast acquisition is matched before the pool
+    assert_eq!(gains_r[0].open_date(), transactions[1].date());
+    assert_eq!(gains_r[0].open_basis(), -750.0);
+    assert_eq!(gains_r[0].close_basis(), 900.0);
+    assert_eq!(gains_r[1].open_basis(), -200.0);
+    assert_eq!(gains_r[2].realized(), -100.0);
+    assert_eq!(holding.position(), (850.0, 4.0, -3400.0));
+}
+
+#[test]
+fn uk_share_matching_rejects_short_holding() {
+    let mut holding = Holding::new(&Transaction::from("2019-01-01,short,1000.0,4.0"));
+    let transactions = [Transaction::from("2020-03-01,long,200.0,6.0")];
+    assert_eq!(
+        holding.extend_transactions_uk(&transactions),
+        Err(Error::ShortHolding(transactions[0].date()))
+    );
+    // short inventory is kept
+    assert_eq!(holding.position(), (-1000.0, 4.0, 4000.0));
+}
+
+#[test]
+fn superficial_loss_with_adjusted_cost_base() {
+    let mut holding = Holding::with_selector(AverageCost);
+    let transactions = [
+        Transaction::from("2020-01-01,long,100.0,50.0"),
+        Transaction::from("2020-02-15,long,50.0,48.0"),
+        Transaction::from("2020-03-01,short,100.0,40.0"),
+    ];
+    let gains_r = holding.extend_transactions_ca(&transactions).unwrap();
+
+    // half of the loss is denied as 50 of the 100 sold were acquired in the period and still held
+    let loss = 4000.0 - 7400.0 * 100.0 / 150.0;
+    assert_eq!(gains_r.len(), 1);
+    assert_eq!(gains_r[0].disallowed(), -loss * 0.5);
+    assert_eq!(gains_r[0].realized(), loss + -loss * 0.5);
+    assert_eq!(holding.position().0, 50.0);
+    // remainder of the pool basis after the sale
+    assert_eq!(
+        holding.position().2,
+        -7400.0 - -7400.0 * 100.0 / 150.0 + loss * 0.5
+    );
+}
+
+#[test]
+fn dust_left_after_a_sale_is_treated_and_recorded() {
+    let transactions = [
+        Transaction::from("2020-01-01,long,10.0,2000.0"),
+        Transaction::from("2020-01-02,long,0.5,2.0"),
+        Transaction::from("2020-02-01,short,10.0,2500.0"),
+        Transaction::from("2020-03-01,long,1.0,3.0"),
+    ];
+    let sale = Realized::from("2020-02-01,-10.0,25000.0,2020-01-01,-20000.0");
+    let event = |treatment| DustEvent {
+        date: transactions[2].date(),
+        quantity: 0.5,
+        basis: -1.0,
+        treatment,
+    };
+    let dust = |treatment| Holding::builder().dust(1.0, treatment).build().unwrap();
+
+    let mut holding = dust(DustTreatment::WriteOff);
+    let gains_r = holding.extend_transactions(&transactions[..3]);
+    assert_eq!(
+        gains_r,
+        [
+            sale.clone(),
+            Realized::from("2020-02-01,-0.5,0.0,2020-01-02,-1.0")
+        ]
+    );
+    assert!(holding.inventory().is_empty());
+    assert_eq!(holding.dust_events(), [event(DustTreatment::WriteOff)]);
+
+    let mut holding = dust(DustTreatment::RollForward);
+    let gains_r = holding.extend_transactions(&transactions);
+    assert_eq!(gains_r[..], [sale][..]);
+    assert_eq!(
+        holding.inventory(),
+        [URealized::from("2020-03-01,1.0,-4.0")]
+    );
+    assert_eq!(holding.dust_events(), [event(DustTreatment::RollForward)]);
+
+    let mut holding = dust(DustTreatment::Keep);
+    holding.extend_transactions(&transactions[..3]);
+    assert_eq!(
+        holding.inventory(),
+        [URealized::from("2020-01-02,0.5,-1.0")]
+    );
+    assert_eq!(holding.dust_events(), [event(DustTreatment::Keep)]);
+
+    // a sale larger than the holding opens a short position that isn't dust
+    let mut holding = dust(DustTreatment::Discard);
+    holding.extend_transactions(&[
+        transactions[0],
+        Transaction::from("2020-02-01,short,10.5,2500.0"),
+    ]);
+    assert_eq!(
+        holding.inventory(),
+        [URealized::from("2020-02-01,-0.5,1250.0")]
+    );
+    assert!(holding.dust_events().is_empty());
+
+    // inventory netting to zero is recorded as dust
+    let mut holding = dust(DustTreatment::Discard);
+    holding.extend_transactions(&[
+        transactions[0],
+        Transaction::from("2020-01-02,long,0.00000000005,2.0"),
+        transactions[2],
+    ]);
+    assert!(holding.inventory().is_empty());
+    assert_eq!(holding.dust_events().len(), 1);
+    assert_eq!(holding.dust_events()[0].quantity, 0.00000000005);
+
+    // no dust threshold by default
+    let mut holding = Holding::default();
+    holding.extend_transactions(&transactions[..3]);
+    assert!(holding.dust_events().is_empty());
+    assert!(Holding::<f64>::builder()
+        .dust(-1.0, DustTreatment::Discard)
+        .build()
+        .is_err());
+    assert!(Holding::<f64>::builder()
+        .dust(0.00000000001, DustTreatment::Discard)
+        .build()
+        .is_err());
+}
+
+#[test]
+fn fees_added_to_cost_and_taken_from_proceeds() {
+    let transactions = [
+        Transaction::from("2020-01-01,long,100.0,25.0,,10.0"),
+        Transaction::from("2020-02-01,long,50.0,30.0").with_fee(5.0),
+        Transaction::from("2020-03-01,short,120.0,35.0,,12.0"),
+    ];
+    let mut holding = Holding::default();
+    let gains_r = holding.extend_transactions(&transactions);
+
+    // sale fee is pro-rated across both lots sold
+    assert_eq!(
+        gains_r,
+        [
+            Realized::from("2020-03-01,-100.0,3490.0,2020-01-01,-2510.0"),
+            Realized::from("2020-03-01,-20.0,698.0,2020-02-01,-602.0"),
+        ]
+    );
+    assert_eq!(
+        holding.inventory(),
+        [URealized::from("2020-02-01,30.0,-903.0")]
+    );
+}
+
+#[test]
+fn gas_fee_paid_in_eth_is_a_disposal_at_market_value() {
+    let mut eth = Holding::new(&Transaction::from("2020-01-01,buy,1.0,1000.0"));
+    let mut token = Holding::default();
+
+    let bought = token
+        .try_add_transaction_with_asset_fee(
+            &Transaction::from("2020-02-01,buy,100.0,2.0"),
+            &mut eth,
+            0.01,
+            1500.0,
+        )
+        .unwrap();
+    assert!(bought.trade.is_empty());
+    assert_eq!(
+        bought.fee,
+        [Realized::from("2020-02-01,-0.01,15.0,2020-01-01,-10.0")]
+    );
+    assert_eq!(
+        token.inventory(),
+        [URealized::from("2020-02-01,100.0,-215.0")]
+    );
+
+    let sold = token
+        .try_add_transaction_with_asset_fee(
+            &Transaction::from("2020-03-01,sell,100.0,3.0"),
+            &mut eth,
+            0.01,
+            2000.0,
+        )
+        .unwrap();
+    assert_eq!(
+        sold.trade,
+        [Realized::from("2020-03-01,-100.0,280.0,2020-02-01,-215.0")]
+    );
+    assert_eq!(sold.fee[0].realized(), 10.0);
+    assert_eq!(eth.position().0, 0.98);
+}
+
+#[test]
+fn portfolio_configures_holding_of_each_asset() {
+    let mut portfolio = Portfolio::new(Holding::with_selector(Lifo));
+    let trades = [
+        ("AAPL", "2020-01-01,buy,10.0,100.0"),
+        ("MSFT", "2020-01-01,buy,5.0,200.0"),
+        ("AAPL", "2020-02-01,buy,10.0,120.0"),
+        ("AAPL", "2020-03-01,sell,10.0,130.0"),
+    ];
+    for (asset, trade) in trades.iter() {
+        portfolio.add_transaction(asset, &Transaction::from(*trade));
+    }
+
+    assert_eq!(
+        portfolio.realized("AAPL"),
+        [Realized::from("2020-03-01,-10.0,1300.0,2020-02-01,-1200.0")]
+    );
+    assert_eq!(portfolio.total_realized(), 100.0);
+    assert_eq!(
+        portfolio.open_positions(),
+        [
+            ("AAPL", (10.0, 100.0, -1000.0)),
+            ("MSFT", (5.0, 200.0, -1000.0))
+        ]
+    );
+}
+
+#[test]
+fn transfer_of_a_specific_lot_keeps_its_basis() {
+    let mut accounts = Accounts::default();
+    accounts.add_transaction(
+        "broker",
+        "AAPL",
+        &Transaction::from("2020-01-01,buy,10.0,100.0"),
+    );
+    accounts.add_transaction(
+        "broker",
+        "AAPL",
+        &Transaction::from("2020-02-01,buy,10.0,120.0"),
+    );
+
+    let lots = accounts
+        .transfer(
+            "AAPL",
+            "broker",
+            "ira",
+            "2020-03-01".parse().unwrap(),
+            4.0,
+            Some(LotRef::from("2020-02-01")),
+        )
+        .unwrap();
+    assert_eq!(lots, [URealized::from("2020-02-01,4.0,-480.0")]);
+
+    let realized = accounts.add_transaction(
+        "ira",
+        "AAPL",
+        &Transaction::from("2020-04-01,sell,4.0,130.0"),
+    );
+    assert_eq!(
+        realized,
+        [Realized::from("2020-04-01,-4.0,520.0,2020-02-01,-480.0")]
+    );
+    assert_eq!(
+        accounts.holding("broker", "AAPL").unwrap().position(),
+        (16.0, 107.5, -1720.0)
+    );
+}
+
+#[test]
+fn eur_trades_reported_in_usd_keep_native_values() {
+    let fx: FxRates = FxRates::from_csv(
+        Currency::from("USD"),
+        "date,currency,rate\n2020-01-01,EUR,1.25\n2020-06-01,EUR,1.5\n",
+    )
+    .unwrap();
+    let mut holding = Holding::default();
+
+    let opened = fx
+        .try_add_transaction(
+            &mut holding,
+            &Transaction::from("2020-01-02,buy,10.0,100.0,,,EUR"),
+        )
+        .unwrap();
+    assert!(opened.is_empty());
+
+    // no gain in EUR is a gain in USD as the EUR strengthened
+    let closed = fx
+        .try_add_transaction(
+            &mut holding,
+            &Transaction::from("2020-06-15,sell,4.0,100.0,,,EUR"),
+        )
+        .unwrap();
+    assert_eq!(closed[0].native.realized(), 0.0);
+    assert_eq!(
+        closed[0].realized,
+        Realized::from("2020-06-15,-4.0,600.0,2020-01-02,-500.0")
+    );
+
+    // a missing rate leaves the holding unchanged
+    assert!(matches!(
+        fx.try_add_transaction(
+            &mut holding,
+            &Transaction::from("2020-07-01,sell,1.0,100.0,,,GBP")
+        ),
+        Err(Error::RateNotFound(_, _))
+    ));
+    assert_eq!(holding.position().0, 6.0);
+}
+
+#[test]
+fn spending_eur_realizes_fx_gain_in_usd() {
+    let fx: FxRates = FxRates::from_csv(
+        Currency::from("USD"),
+        "2020-01-01,EUR,1.25\n2020-06-01,EUR,1.5\n",
+    )
+    .unwrap();
+    let trades = [
+        Transaction::from("2020-01-02,sell,10.0,100.0,,,EUR"),
+        Transaction::from("2020-03-01,buy,5.0,20.0"),
+        Transaction::from("2020-06-15,buy,4.0,100.0,,,EUR"),
+    ];
+    let mut cash = Portfolio::default();
+
+    let fx_gains = fx.try_add_cash_legs(&mut cash, &trades).unwrap();
+    assert_eq!(
+        fx_gains,
+        [Realized::from("2020-06-15,-400.0,600.0,2020-01-02,-500.0")]
+    );
+    assert_eq!(cash.assets().collect::<Vec<_>>(), ["EUR"]);
+    assert_eq!(cash.position("EUR"), Some((600.0, 1.25, -750.0)));
+
+    // spending more EUR than held doesn't open a short currency position
+    let spend = [Transaction::from("2020-07-01,buy,10.0,100.0,,,EUR")];
+    assert_eq!(
+        fx.try_add_cash_legs(&mut cash, &spend),
+        Err(Error::InsufficientHolding(spend[0].date(), 1000.0, 600.0))
+    );
+    assert_eq!(cash.position("EUR"), Some((600.0, 1.25, -750.0)));
+}
+
+#[cfg(feature = "decimal")]
+#[test]
+fn decimal_holding_reconciles_cents_exactly() {
+    use costbasis::num::Decimal;
+    use costbasis::realized::total_realized;
+
+    let transactions: Vec<Transaction<Decimal>> = [
+        "2020-01-01,long,0.1,0.7",
+        "2020-01-02,long,0.2,0.3",
+        "2020-02-01,short,0.3,10.01",
+    ]
+    .iter()
+    .map(|t| t.parse().unwrap())
+    .collect();
+    let mut holding = Holding::default();
+    let gains_r = holding.extend_transactions(&transactions);
+
+    // proceeds 3.003 less cost 0.07 and 0.06, which f64 can't represent exactly
+    assert_eq!(total_realized(&gains_r), Decimal::new(2873, 3));
+    assert_eq!(gains_r[0].open_basis(), Decimal::new(-7, 2));
+    assert!(holding.inventory().is_empty());
+}
+
+mod basis_split {
+    use super::*;
+    use costbasis::config::BasisSplit;
+    use costbasis::num::Number;
+    use proptest::prelude::*;
+
+    // purchases of `lots` of (quantity, cents) followed by `sales` never larger than the quantity held
+    fn batch<N>(lots: &[(u32, u32)], sales: &[u32]) -> Vec<Transaction<N>>
+    where
+        N: Number,
+    {
+        let mut transactions = Vec::new();
+        let mut held = 0;
+        for (i, (quantity, cents)) in lots.iter().enumerate() {
+            let t = format!(
+                "2020-01-{:02},long,{},{}.{:02}",
+                i + 1,
+                quantity,
+                cents / 100,
+                cents % 100
+            );
+            transactions.push(t.parse().unwrap());
+            held += quantity;
+        }
+        for (i, sale) in sales.iter().enumerate() {
+            let sale = (*sale).min(held);
+            if sale == 0 {
+                break;
+            }
+            let t = format!("2021-01-{:02},short,{},12.34", i + 1, sale);
+            transactions.push(t.parse().unwrap());
+            held -= sale;
+        }
+        transactions
+    }
+
+    // holding of `N` splitting the basis rounded to `decimals`, pooled or FIFO
+    fn holding<N>(decimals: u32, pooled: bool) -> Holding<N>
+    where
+        N: Number,
+    {
+        let builder = Holding::builder().basis_split(BasisSplit::Rounded(decimals));
+        if pooled {
+            builder.selector(AverageCost)
+        } else {
+            builder
+        }
+        .build()
+        .unwrap()
+    }
+
+    proptest! {
+        // total cost of all realized plus remaining inventory equals the purchase cost to f64 precision
+        #[test]
+        fn partial_sales_keep_purchase_cost(
+            lots in prop::collection::vec((1u32..10_000, 1u32..100_000), 1..5),
+            sales in prop::collection::vec(1u32..3_000, 1..20),
+            decimals in 0u32..9,
+            pooled in any::<bool>(),
+        ) {
+            let mut holding: Holding = holding(decimals, pooled);
+            let transactions: Vec<Transaction> = batch(&lots, &sales);
+            let cost: f64 = transactions
+                .iter()
+                .filter(|t| t.itype() == InventoryType::Long)
+                .map(|t| t.basis())
+                .sum();
+
+            let gains_r = holding.extend_transactions(&transactions);
+            let realized_cost: f64 = gains_r.iter().map(|r| r.open_basis()).sum();
+            prop_assert!((realized_cost + holding.position().2 - cost).abs() <= cost.abs() * 1e-9);
+        }
+
+        // total cost of all realized plus remaining inventory always equals the purchase cost
+        #[cfg(feature = "decimal")]
+        #[test]
+        fn partial_sales_keep_purchase_cost_exactly(
+            lots in prop::collection::vec((1u32..10_000, 1u32..100_000), 1..5),
+            sales in prop::collection::vec(1u32..3_000, 1..20),
+            decimals in 0u32..9,
+            pooled in any::<bool>(),
+        ) {
+            use costbasis::num::Decimal;
+
+            let mut holding: Holding<Decimal> = holding(decimals, pooled);
+            let transactions: Vec<Transaction<Decimal>> = batch(&lots, &sales);
+            let cost: Decimal = transactions
+                .iter()
+                .filter(|t| t.itype() == InventoryType::Long)
+                .map(|t| t.basis())
+                .sum();
+
+            let gains_r = holding.extend_transactions(&transactions);
+            let realized_cost: Decimal = gains_r.iter().map(|r| r.open_basis()).sum();
+            prop_assert_eq!(realized_cost + holding.position().2, cost);
+            if decimals >= 2 {
+                prop_assert!(gains_r
+                    .iter()
+                    .all(|r| r.open_basis().round_dp(decimals) == r.open_basis()));
+            }
+        }
+    }
 }